@@ -1,28 +1,48 @@
 mod network;
 mod persist;
+mod machine;
 
 pub use network::*;
 pub use persist::*;
+pub use machine::*;
 
 mod raft_nums;
+mod raft_entry;
+mod raft_outcome;
 pub use raft_nums::*;
+pub use raft_entry::*;
+pub use raft_outcome::*;
 
 // Implementation of 'Paper Raft'
 mod raft_paper_impl;
 mod raft_paper_message;
 mod raft_paper_proposal;
 mod raft_paper_election;
+mod raft_paper_snapshot;
+mod raft_paper_transfer;
+mod raft_paper_read;
 pub use raft_paper_impl::*;
 pub use raft_paper_message::*;
-pub(crate) use raft_paper_proposal::*;
-pub(crate) use raft_paper_election::*;
+pub(crate) use raft_paper_read::*;
 
 // Implementation of 'Luby Transform Raft'
 mod raft_luby_impl;
 mod raft_luby_message;
 mod raft_luby_proposal;
 mod raft_luby_election;
+mod raft_luby_decode;
+mod raft_luby_snapshot;
+mod raft_luby_degree;
+mod raft_luby_precode;
+mod raft_luby_scheme;
+mod raft_luby_rlnc;
+mod raft_luby_bytes;
 pub use raft_luby_impl::*;
 pub use raft_luby_message::*;
-pub(crate) use raft_luby_proposal::*;
-pub(crate) use raft_luby_election::*;
+pub use raft_luby_degree::*;
+pub use raft_luby_precode::*;
+pub use raft_luby_scheme::*;
+pub use raft_luby_rlnc::*;
+pub use raft_luby_bytes::*;
+pub(crate) use raft_luby_decode::*;
+pub(crate) use raft_luby_snapshot::*;
@@ -0,0 +1,145 @@
+use crate::*;
+use std::collections::BTreeMap;
+
+pub trait StateMachine<Proposal> {
+    /// apply a committed proposal at a log index, and return the response to the client
+    /// every proposal is applied exactly once, in log order
+    fn apply(&mut self, index: usize, proposal: Proposal, id: ProposalId) -> Vec<u8>;
+    /// take a snapshot of the state, covering every proposal applied so far
+    fn snapshot(&self) -> Vec<u8>;
+    /// replace the state by a snapshot covering log entries 0..at
+    fn restore(&mut self, at: usize, snapshot: Vec<u8>);
+}
+
+// Client sessions on top of a state machine, so that a retried command takes effect once
+// - a client numbers its commands 1, 2, ... with ProposalId::session, and retries under the same id
+// - a client has at most one outstanding command, so a command numbered no later than
+//   the latest applied one of its client is a duplicate, and is skipped
+// - the response of the latest command of each client is cached for retries
+// - sessions are part of snapshots, so deduplication survives restarts and installed snapshots
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sessions<Machine> {
+    pub machine: Machine,
+    // client -> latest sequence number applied, and its response
+    sessions: BTreeMap<u32, (u32, Vec<u8>)>,
+}
+
+impl<Machine> Sessions<Machine> {
+    pub fn new(machine: Machine) -> Self {
+        Self { machine, sessions: BTreeMap::new() }
+    }
+    // the cached response of a command, if it is the latest applied one of its client
+    pub fn response(&self, id: ProposalId) -> Option<&[u8]> {
+        let (seq, response) = self.sessions.get(&id.client())?;
+        (*seq == id.seq()).then_some(response.as_slice())
+    }
+}
+
+impl<Proposal, Machine: StateMachine<Proposal>> StateMachine<Proposal> for Sessions<Machine> {
+    fn apply(&mut self, index: usize, proposal: Proposal, id: ProposalId) -> Vec<u8> {
+        if id.client() == 0 { return self.machine.apply(index, proposal, id) }
+        match self.sessions.get(&id.client()) {
+            Some((seq, response)) if *seq == id.seq() => response.clone(),
+            // an older command, whose response the client has already got
+            Some((seq, _)) if *seq > id.seq() => vec![],
+            _ => {
+                let response = self.machine.apply(index, proposal, id);
+                self.sessions.insert(id.client(), (id.seq(), response.clone()));
+                response
+            }
+        }
+    }
+    // sessions: count, then (client, seq, length, response) for each, then the inner snapshot
+    fn snapshot(&self) -> Vec<u8> {
+        let mut snapshot = (self.sessions.len() as u64).to_le_bytes().to_vec();
+        for (client, (seq, response)) in self.sessions.iter() {
+            snapshot.extend((*client as u64).to_le_bytes());
+            snapshot.extend((*seq as u64).to_le_bytes());
+            snapshot.extend((response.len() as u64).to_le_bytes());
+            snapshot.extend(response);
+        }
+        snapshot.extend(self.machine.snapshot());
+        snapshot
+    }
+    fn restore(&mut self, at: usize, snapshot: Vec<u8>) {
+        fn take<'a>(rest: &mut &'a [u8], len: usize) -> &'a [u8] {
+            let (x, tail) = rest.split_at(len);
+            *rest = tail;
+            x
+        }
+        let word = |rest: &mut &[u8]| u64::from_le_bytes(take(rest, 8).try_into().unwrap()) as usize;
+        let mut rest = snapshot.as_slice();
+        self.sessions.clear();
+        for _ in 0..word(&mut rest) {
+            let (client, seq, len) = (word(&mut rest) as u32, word(&mut rest) as u32, word(&mut rest));
+            self.sessions.insert(client, (seq, take(&mut rest, len).to_vec()));
+        }
+        self.machine.restore(at, rest.to_vec());
+    }
+}
+
+// Mock state machine, which records where each proposal is applied
+// - the response is the log index, in little endian
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockStateMachine {
+    pub applied: Vec<(usize, ProposalId)>,
+}
+
+impl MockStateMachine {
+    pub fn new() -> Self {
+        Self { applied: vec![] }
+    }
+}
+
+impl<Proposal> StateMachine<Proposal> for MockStateMachine {
+    fn apply(&mut self, index: usize, _proposal: Proposal, id: ProposalId) -> Vec<u8> {
+        self.applied.push((index, id));
+        (index as u64).to_le_bytes().to_vec()
+    }
+    fn snapshot(&self) -> Vec<u8> {
+        self.applied.iter().flat_map(|(index, id)| [(*index as u64).to_le_bytes(), id.0.to_le_bytes()]).flatten().collect()
+    }
+    fn restore(&mut self, _at: usize, snapshot: Vec<u8>) {
+        let word = |x: &[u8]| u64::from_le_bytes(x.try_into().unwrap());
+        self.applied = snapshot.chunks(16).map(|x| (word(&x[..8]) as usize, ProposalId(word(&x[8..])))).collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mock_restore() {
+        let mut machine = MockStateMachine::new();
+        for i in 0..5 { StateMachine::<usize>::apply(&mut machine, i * 2, i, ProposalId(i as u64)); }
+        let mut restored = MockStateMachine::new();
+        StateMachine::<usize>::restore(&mut restored, 9, StateMachine::<usize>::snapshot(&machine));
+        assert_eq!(restored, machine);
+    }
+
+    #[test]
+    fn session_dedup() {
+        let mut sessions = Sessions::new(MockStateMachine::new());
+        let apply = |sessions: &mut Sessions<MockStateMachine>, index: usize, id: ProposalId|
+            StateMachine::<usize>::apply(sessions, index, 0, id);
+        let (a, b) = (ProposalId::session(1, 1), ProposalId::session(2, 1));
+        assert_eq!(apply(&mut sessions, 0, a), 0u64.to_le_bytes());
+        assert_eq!(apply(&mut sessions, 1, b), 1u64.to_le_bytes());
+        // a retry gets the cached response, and is not applied again
+        assert_eq!(apply(&mut sessions, 2, a), 0u64.to_le_bytes());
+        assert_eq!(apply(&mut sessions, 3, ProposalId::session(1, 2)), 3u64.to_le_bytes());
+        assert_eq!(apply(&mut sessions, 4, a), vec![]);
+        assert_eq!(sessions.response(a), None);
+        // ids without a session are always applied
+        apply(&mut sessions, 5, ProposalId(7));
+        apply(&mut sessions, 6, ProposalId(7));
+        assert_eq!(sessions.machine.applied.iter().map(|x| x.0).collect::<Vec<_>>(), vec![0, 1, 3, 5, 6]);
+        // sessions survive a snapshot
+        let mut restored = Sessions::new(MockStateMachine::new());
+        StateMachine::<usize>::restore(&mut restored, 7, StateMachine::<usize>::snapshot(&sessions));
+        assert_eq!(restored, sessions);
+        assert_eq!(apply(&mut restored, 7, b), 1u64.to_le_bytes());
+        assert_eq!(restored.machine.applied.len(), 5);
+    }
+}
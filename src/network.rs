@@ -49,6 +49,7 @@ pub struct MockBurstNetwork<Msg> {
     flip_upper: f32,
     flip_lower: f32,
     // message delay and timestamp
+    #[allow(dead_code)]
     delay: usize,
     timestamp: usize,
     timedelta_probability: f32
@@ -108,7 +109,7 @@ impl<Msg: Ord + Debug> MockNetwork<Msg> for Arc<Mutex<MockBurstNetwork<Msg>>> {
             println!("NETWORK :: {sender:?} -> {to:?} :: ERASE MESSAGE");
         }
         let arrive = lock.timestamp;
-        if !erase { lock.queue.get_mut(&to).unwrap().push((Reverse(arrive), msg.try_into().unwrap())); }
+        if !erase { lock.queue.get_mut(&to).unwrap().push((Reverse(arrive), msg)); }
         let flip =
             if lock.state[&(sender,to)] { rand::random::<f32>() < lock.flip_upper }
             else { rand::random::<f32>() <= lock.flip_lower };
@@ -6,34 +6,64 @@ pub trait Persistor<Proposal> {
     fn persist(&mut self, term: Term, vote: Option<RaftId>);
     /// load persisted state
     fn load(&mut self) -> (Term, Option<RaftId>);
-    /// push a proposal to local log
-    fn push(&mut self, proposal: Proposal, id: ProposalId, term: Term);
+    /// push an entry to local log
+    fn push(&mut self, entry: Entry<Proposal>, id: ProposalId, term: Term);
     /// access last log item
+    /// fall back to the snapshot point if the log is empty
     fn last(&self) -> (Term, usize);
     /// get term at a given position
+    /// the last position covered by the snapshot is still known
     fn term(&self, at: usize) -> Option<Term>;
+    /// get proposal id at a given position
+    /// entries covered by the snapshot are not included
+    fn id(&self, at: usize) -> Option<ProposalId>;
     /// append / overwrite from a start position
-    fn append(&mut self, at: usize, patch: Vec<(Proposal, ProposalId, Term)>) -> usize;
+    /// entries covered by the snapshot are skipped
+    fn append(&mut self, at: usize, patch: Vec<(Entry<Proposal>, ProposalId, Term)>) -> usize;
+    /// take ids of entries overwritten by append or dropped by compact since the last call
+    fn discarded(&mut self) -> Vec<ProposalId>;
     /// mark entries 0..=at as commitable
     /// return last applied index
     fn commit(&mut self, at: usize);
     /// get last commitable
     fn commitable(&self) -> usize;
     /// copy a slice of range
-    fn slice(&mut self, range: std::ops::Range<usize>) -> Vec<(Proposal, ProposalId, Term)>;
+    /// entries covered by the snapshot are not included
+    fn slice(&mut self, range: std::ops::Range<usize>) -> Vec<(Entry<Proposal>, ProposalId, Term)>;
+    /// save a snapshot of entries 0..at, where the entry at - 1 has the given term
+    /// this must be synchronous
+    /// - if the log has that entry, the log prefix is discarded
+    /// - otherwise, the whole log is discarded, and its entries count as overwritten
+    fn compact(&mut self, at: usize, term: Term, snapshot: Vec<u8>);
+    /// the snapshot point, (Term(0), 0) if there is no snapshot
+    fn offset(&self) -> (Term, usize);
+    /// load latest snapshot
+    fn snapshot(&self) -> Option<Vec<u8>>;
 }
 
 #[derive(Debug, Clone)]
 pub struct MockPersistor<Proposal> {
     commit: usize,
-    log: Vec<(Proposal, ProposalId, Term)>,
+    // log entries after the snapshot point
+    log: Vec<(Entry<Proposal>, ProposalId, Term)>,
     vote: Option<RaftId>,
-    term: Term
+    term: Term,
+    // snapshot point and data
+    offset: (Term, usize),
+    snapshot: Option<Vec<u8>>,
+    // ids of overwritten entries
+    discarded: Vec<ProposalId>,
 }
 
 impl<Proposal: Clone> MockPersistor<Proposal> {
     pub fn new() -> Self {
-        Self { commit: 0, log: vec![], vote: None, term: Term(0) }
+        Self { commit: 0, log: vec![], vote: None, term: Term(0), offset: (Term(0), 0), snapshot: None, discarded: vec![] }
+    }
+}
+
+impl<Proposal: Clone> Default for MockPersistor<Proposal> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -45,28 +75,40 @@ impl<Proposal: Clone> Persistor<Proposal> for MockPersistor<Proposal> {
     fn load(&mut self) -> (Term, Option<RaftId>) {
         (self.term, self.vote)
     }
-    fn push(&mut self, proposal: Proposal, id: ProposalId, term: Term) {
-        self.log.push((proposal, id, term));
+    fn push(&mut self, entry: Entry<Proposal>, id: ProposalId, term: Term) {
+        self.log.push((entry, id, term));
     }
     fn last(&self) -> (Term, usize) {
-        self.log.last().map(|(_, _, term)| (*term, self.log.len())).unwrap_or((Term(0), 0))
+        self.log.last().map(|(_, _, term)| (*term, self.offset.1 + self.log.len())).unwrap_or(self.offset)
     }
     fn term(&self, at: usize) -> Option<Term> {
-        self.log.get(at).map(|(_, _, term)| *term)
-    }
-    fn append(&mut self, at: usize, patch: Vec<(Proposal, ProposalId, Term)>) -> usize {
-        let mut end = at;
-        for (delta, (proposal, id, term)) in patch.into_iter().enumerate() {
-            if let Some(entry) = self.log.get(at + delta) {
-                if entry.2 != term { self.log.resize_with(at + delta, || panic!()); }
-                else { end = at + delta + 1; }
+        if at + 1 == self.offset.1 { return Some(self.offset.0) }
+        self.log.get(at.checked_sub(self.offset.1)?).map(|(_, _, term)| *term)
+    }
+    fn id(&self, at: usize) -> Option<ProposalId> {
+        self.log.get(at.checked_sub(self.offset.1)?).map(|(_, id, _)| *id)
+    }
+    fn append(&mut self, at: usize, patch: Vec<(Entry<Proposal>, ProposalId, Term)>) -> usize {
+        let offset = self.offset.1;
+        let mut end = at.max(offset);
+        for (delta, (entry, id, term)) in patch.into_iter().enumerate() {
+            // entries in snapshot are committed, so they always match
+            let Some(at) = (at + delta).checked_sub(offset) else { continue };
+            if let Some(entry) = self.log.get(at) {
+                if entry.2 != term {
+                    self.discarded.extend(self.log.drain(at..).map(|(_, id, _)| id));
+                }
+                else { end = offset + at + 1; }
             }
-            if at + delta == self.log.len() {
-                self.log.push((proposal, id, term));
-                end = at + delta + 1;
+            if at == self.log.len() {
+                self.log.push((entry, id, term));
+                end = offset + at + 1;
             }
         }
-        return end;
+        end
+    }
+    fn discarded(&mut self) -> Vec<ProposalId> {
+        std::mem::take(&mut self.discarded)
     }
     fn commit(&mut self, at: usize) {
         self.commit = self.commit.max(at);
@@ -74,9 +116,56 @@ impl<Proposal: Clone> Persistor<Proposal> for MockPersistor<Proposal> {
     fn commitable(&self) -> usize {
         self.commit
     }
-    fn slice(&mut self, mut range: std::ops::Range<usize>) -> Vec<(Proposal, ProposalId, Term)> {
-        range.end = range.end.min(self.log.len());
-        range.start = range.start.min(range.end);
+    fn slice(&mut self, mut range: std::ops::Range<usize>) -> Vec<(Entry<Proposal>, ProposalId, Term)> {
+        range.end = range.end.saturating_sub(self.offset.1).min(self.log.len());
+        range.start = range.start.saturating_sub(self.offset.1).min(range.end);
         self.log[range].to_vec()
     }
-}
\ No newline at end of file
+    fn compact(&mut self, at: usize, term: Term, snapshot: Vec<u8>) {
+        if at <= self.offset.1 { return }
+        if self.term(at - 1) == Some(term) { self.log.drain(..at - self.offset.1); }
+        else { self.discarded.extend(self.log.drain(..).map(|(_, id, _)| id)); }
+        self.offset = (term, at);
+        self.snapshot = Some(snapshot);
+        self.commit = self.commit.max(at);
+    }
+    fn offset(&self) -> (Term, usize) {
+        self.offset
+    }
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        self.snapshot.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mock_compact() {
+        let mut disk = MockPersistor::<usize>::new();
+        for i in 0..10 { disk.push(Entry::Command(i), ProposalId(i as u64), Term(i as u64 / 4)); }
+        disk.commit(6);
+        disk.compact(6, Term(1), vec![6]);
+        assert_eq!(disk.offset(), (Term(1), 6));
+        assert_eq!(disk.last(), (Term(2), 10));
+        assert_eq!(disk.term(4), None);
+        assert_eq!(disk.term(5), Some(Term(1)));
+        assert_eq!(disk.term(8), Some(Term(2)));
+        assert_eq!(disk.id(5), None);
+        assert_eq!(disk.id(8), Some(ProposalId(8)));
+        assert_eq!(disk.id(10), None);
+        assert_eq!(disk.slice(0..8).into_iter().map(|x| x.0).collect::<Vec<_>>(), vec![Entry::Command(6), Entry::Command(7)]);
+        // entries in snapshot are skipped, conflicting entries are overwritten
+        let patch = (4..9).map(|i| (Entry::Command(i), ProposalId(i as u64), Term(if i < 8 { i as u64 / 4 } else { 3 }))).collect();
+        assert_eq!(disk.append(4, patch), 9);
+        assert_eq!(disk.last(), (Term(3), 9));
+        // a snapshot beyond the log discards everything
+        disk.discarded();
+        disk.compact(20, Term(5), vec![20]);
+        assert_eq!(disk.discarded(), vec![ProposalId(6), ProposalId(7), ProposalId(8)]);
+        assert_eq!(disk.last(), (Term(5), 20));
+        assert_eq!(disk.slice(0..30), vec![]);
+        assert_eq!(disk.snapshot(), Some(vec![20]));
+    }
+}
@@ -0,0 +1,215 @@
+//! Log entries and cluster membership
+use crate::*;
+
+// An item in the replicated log
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Entry<Proposal> {
+    // a client proposal
+    Command(Proposal),
+    // a membership change, in effect as soon as it is in the log
+    Config(Config),
+    // an empty entry, appended by a new leader to commit entries of earlier terms
+    Noop,
+}
+
+// Cluster membership
+// - voters: servers counted in commit and election quorums
+// - joint: voters of the next configuration, during joint consensus C_old,new
+//   a quorum needs a majority of both voters and joint
+// - learners: servers that receive log entries, but are counted in no quorum
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Config {
+    pub(crate) voters: Vec<RaftId>,
+    pub(crate) joint: Option<Vec<RaftId>>,
+    pub(crate) learners: Vec<RaftId>,
+}
+
+impl Config {
+    pub fn new(mut voters: Vec<RaftId>) -> Self {
+        voters.sort();
+        voters.dedup();
+        Self { voters, joint: None, learners: vec![] }
+    }
+    // the joint configuration C_old,new that moves to given voters
+    // - learners among the new voters are promoted
+    pub fn enter(&self, voters: Vec<RaftId>) -> Self {
+        let joint = Self::new(voters).voters;
+        let learners = self.learners.iter().filter(|x| !joint.contains(x)).copied().collect();
+        Self { voters: self.voters.clone(), joint: Some(joint), learners }
+    }
+    // the configuration C_new that ends joint consensus
+    pub fn leave(&self) -> Self {
+        let voters = self.joint.clone().unwrap_or_else(|| self.voters.clone());
+        Self { learners: self.learners.clone(), ..Self::new(voters) }
+    }
+    // the configuration with a server as a learner instead of a voter
+    pub fn learn(&self, id: RaftId) -> Self {
+        let mut config = self.clone();
+        config.voters.retain(|x| *x != id);
+        if let Some(joint) = &mut config.joint { joint.retain(|x| *x != id) }
+        if let Err(i) = config.learners.binary_search(&id) { config.learners.insert(i, id) }
+        config
+    }
+    // every server that should receive log entries
+    pub fn members(&self) -> Vec<RaftId> {
+        let mut members = self.voters.iter().chain(self.joint.iter().flatten()).chain(self.learners.iter()).copied().collect::<Vec<_>>();
+        members.sort();
+        members.dedup();
+        members
+    }
+    // whether a server is counted in some quorum
+    pub fn is_voter(&self, id: RaftId) -> bool {
+        self.voters.contains(&id) || self.joint.iter().flatten().any(|x| *x == id)
+    }
+    // whether the given servers form a quorum
+    pub fn quorum(&self, granted: impl Fn(RaftId) -> bool) -> bool {
+        let majority = |voters: &[RaftId]| 2 * voters.iter().filter(|x| granted(**x)).count() > voters.len();
+        majority(&self.voters) && self.joint.as_deref().is_none_or(majority)
+    }
+    // the largest index matched by a quorum
+    pub fn committed(&self, matched: impl Fn(RaftId) -> usize) -> usize {
+        let majority = |voters: &[RaftId]| {
+            let mut matches = voters.iter().map(|x| matched(*x)).collect::<Vec<_>>();
+            matches.sort_by(|a, b| b.cmp(a));
+            matches.get(voters.len() / 2).copied().unwrap_or(usize::MAX)
+        };
+        majority(&self.voters).min(self.joint.as_deref().map_or(usize::MAX, majority))
+    }
+}
+
+// Configuration entries of local log
+// - the latest entry is in effect, even before it is committed
+// - entries covered by the snapshot fold into a base configuration
+#[derive(Debug, Clone)]
+pub(crate) struct Membership {
+    base: Config,
+    entries: Vec<(usize, Config)>,
+}
+
+impl Membership {
+    // recover configuration entries after the snapshot point
+    pub(crate) fn load<Proposal>(base: Config, disk: &mut impl Persistor<Proposal>) -> Self {
+        let mut membership = Self { base, entries: vec![] };
+        membership.scan(disk.offset().1, disk);
+        membership
+    }
+    // the configuration in effect at the snapshot point
+    pub(crate) fn base(&self) -> &Config {
+        &self.base
+    }
+    // the configuration in effect
+    pub(crate) fn config(&self) -> &Config {
+        self.entries.last().map(|(_, x)| x).unwrap_or(&self.base)
+    }
+    // log index of the configuration in effect, none if it is covered by the snapshot
+    pub(crate) fn index(&self) -> Option<usize> {
+        self.entries.last().map(|(at, _)| *at)
+    }
+    // replace the base configuration, e.g. before anything is learnt from the leader
+    pub(crate) fn rebase(&mut self, base: Config) {
+        self.base = base;
+    }
+    // record a configuration entry pushed to local log
+    pub(crate) fn push(&mut self, at: usize, config: Config) {
+        self.entries.push((at, config));
+    }
+    // rescan local log from a position, after entries there are appended or overwritten
+    pub(crate) fn scan<Proposal>(&mut self, at: usize, disk: &mut impl Persistor<Proposal>) {
+        self.entries.retain(|(x, _)| *x < at);
+        let at = at.max(disk.offset().1);
+        for (i, (entry, _, _)) in disk.slice(at..disk.last().1).into_iter().enumerate() {
+            if let Entry::Config(config) = entry { self.entries.push((at + i, config)) }
+        }
+    }
+    // fold entries before a snapshot point into the base configuration
+    pub(crate) fn compact(&mut self, at: usize) {
+        let split = self.entries.partition_point(|(x, _)| *x < at);
+        if let Some((_, config)) = self.entries.drain(..split).next_back() { self.base = config }
+    }
+    // replace the base configuration by one of an installed snapshot
+    pub(crate) fn install<Proposal>(&mut self, base: Config, disk: &mut impl Persistor<Proposal>) {
+        self.rebase(base);
+        self.scan(0, disk);
+    }
+}
+
+// Where a follower log conflicts with a prefix 0..at of the leader log, reported on rejection
+// - the term of the follower entry at - 1, and the first index of that term
+// - none and the length of the follower log, if it is shorter than the prefix
+pub(crate) fn conflict<Proposal>(at: usize, disk: &mut impl Persistor<Proposal>) -> (Option<Term>, usize) {
+    let last = disk.last().1;
+    if last < at { return (None, last) }
+    let term = disk.term(at - 1);
+    let mut first = at - 1;
+    while first > disk.offset().1 && disk.term(first - 1) == term { first -= 1 }
+    (term, first)
+}
+
+// Where the leader continues replication after a rejected prefix 0..at, given the conflict hint
+// - past its last entry of the conflicting term, if it has any
+// - otherwise at the first index of that term in the follower log, or at the end of a shorter follower log
+pub(crate) fn backtrack<Proposal>(at: usize, (term, first): (Option<Term>, usize), disk: &mut impl Persistor<Proposal>) -> usize {
+    let Some(term) = term else { return first.min(at) };
+    // terms never decrease along the log
+    let mut last = at.min(disk.last().1);
+    while last > first && disk.term(last - 1).is_some_and(|x| x > term) { last -= 1 }
+    if last > first && disk.term(last - 1) == Some(term) { last.min(at - 1) } else { first }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn joint_quorum() {
+        let config = Config::new((0..3).map(RaftId).collect()).enter((2..5).map(RaftId).collect());
+        assert_eq!(config.members(), (0..5).map(RaftId).collect::<Vec<_>>());
+        assert!(config.is_voter(RaftId(0)) && !config.leave().is_voter(RaftId(0)));
+        // a majority of old voters alone is not enough
+        assert!(!config.quorum(|x| x.0 < 2));
+        assert!(config.quorum(|x| x.0 == 1 || x.0 == 2 || x.0 == 3));
+        // matched: 0 -> 9, 1 -> 8, ..., 4 -> 5
+        assert_eq!(config.committed(|x| 9 - x.0 as usize), 6);
+        assert_eq!(config.leave().committed(|x| 9 - x.0 as usize), 6);
+        assert_eq!(config.leave(), Config::new((2..5).map(RaftId).collect()));
+    }
+
+    #[test]
+    fn learner_quorum() {
+        let config = Config::new((0..3).map(RaftId).collect()).learn(RaftId(3));
+        assert_eq!(config.members(), (0..4).map(RaftId).collect::<Vec<_>>());
+        assert!(!config.is_voter(RaftId(3)));
+        // learners are neither counted in votes nor in commit index
+        assert!(!config.quorum(|x| x.0 == 0 || x.0 == 3));
+        assert_eq!(config.committed(|x| if x.0 == 3 { 9 } else { x.0 as usize }), 1);
+        // a learner is promoted once it is among new voters
+        let config = config.enter((1..4).map(RaftId).collect());
+        assert!(config.learners.is_empty());
+        assert_eq!(config.leave(), Config::new((1..4).map(RaftId).collect()));
+        // learners stay through an unrelated change
+        let config = Config::new((0..3).map(RaftId).collect()).learn(RaftId(3)).enter((0..2).map(RaftId).collect());
+        assert_eq!(config.leave().learners, vec![RaftId(3)]);
+    }
+
+    #[test]
+    fn backtrack_hint() {
+        let log = |terms: &[u64]| {
+            let mut disk = MockPersistor::<usize>::new();
+            for (i, term) in terms.iter().enumerate() { disk.push(Entry::Command(i), ProposalId(i as u64), Term(*term)); }
+            disk
+        };
+        let mut leader = log(&[1, 1, 1, 4, 4, 5, 5, 6, 6, 6]);
+        // the follower skips its whole conflicting term per round trip
+        let mut follower = log(&[1, 1, 1, 2, 2, 2, 3, 3, 3, 3, 3]);
+        assert_eq!(conflict(10, &mut follower), (Some(Term(3)), 6));
+        assert_eq!(backtrack(10, (Some(Term(3)), 6), &mut leader), 6);
+        assert_eq!(conflict(6, &mut follower), (Some(Term(2)), 3));
+        assert_eq!(backtrack(6, (Some(Term(2)), 3), &mut leader), 3);
+        // a shorter follower log
+        assert_eq!(conflict(10, &mut log(&[1, 1, 1, 4])), (None, 4));
+        assert_eq!(backtrack(10, (None, 4), &mut leader), 4);
+        // the leader has entries of the conflicting term
+        assert_eq!(conflict(7, &mut log(&[1, 1, 1, 4, 4, 4, 4])), (Some(Term(4)), 3));
+        assert_eq!(backtrack(7, (Some(Term(4)), 3), &mut leader), 5);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::ops::BitXor;
+
+// Byte buffer proposal for luby raft
+// - xor pads the shorter buffer with zeros
+// - the original length is xor-ed along, so a decoded buffer can be trimmed
+// - trailing zeros are never stored, so equal buffers always compare equal
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CodedBytes {
+    len: u64,
+    data: Vec<u8>,
+}
+
+impl CodedBytes {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        let data = bytes.into();
+        Self { len: data.len() as u64, data }.trim()
+    }
+    // length of original buffer
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    // original buffer, with padding restored or removed
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        data.resize(self.len(), 0);
+        data
+    }
+    fn trim(mut self) -> Self {
+        while self.data.last() == Some(&0) { self.data.pop(); }
+        self
+    }
+}
+
+impl From<Vec<u8>> for CodedBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<CodedBytes> for Vec<u8> {
+    fn from(bytes: CodedBytes) -> Self {
+        bytes.to_vec()
+    }
+}
+
+impl BitXor for CodedBytes {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let (mut long, short) = if self.data.len() >= rhs.data.len() { (self, rhs) } else { (rhs, self) };
+        for (a, b) in long.data.iter_mut().zip(short.data) { *a ^= b; }
+        long.len ^= short.len;
+        long.trim()
+    }
+}
+
+impl Galois for CodedBytes {
+    fn scale(&self, c: u8) -> Self {
+        Self { len: self.len.scale(c), data: self.data.iter().map(|x| x.scale(c)).collect() }.trim()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn xor_padding() {
+        let a = CodedBytes::new(b"hello world".to_vec());
+        let b = CodedBytes::new(b"raft".to_vec());
+        let c = CodedBytes::new(vec![0, 0, 0]);
+        assert_eq!((a.clone() ^ b.clone() ^ b.clone()).to_vec(), b"hello world");
+        assert_eq!((a.clone() ^ b.clone() ^ a.clone()).to_vec(), b"raft");
+        // trailing zeros survive as length only
+        assert_eq!((a.clone() ^ c.clone() ^ a.clone()).to_vec(), vec![0, 0, 0]);
+        assert_eq!(a.clone() ^ c.clone() ^ c.clone(), a);
+        assert_eq!(a.scale(7).scale(gf_inv(7)), a);
+    }
+}
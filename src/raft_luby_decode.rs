@@ -0,0 +1,281 @@
+use crate::*;
+use std::{collections::HashMap, hash::{DefaultHasher, Hash, Hasher}, ops::BitXor};
+
+// Peeling (belief propagation) decoder: 
+// - A codeword is reduced by xor-ing out every source symbol that is already known. 
+// - A codeword reduced to degree 1 reveals a source symbol. 
+// - A revealed symbol is xor-ed out of other buffered codewords, which may reveal more symbols. 
+// - Codewords that are still above degree 1 are kept until more symbols arrive. 
+// Gaussian elimination over GF(2), when peeling gets stuck: 
+// - Buffered codewords are eliminated into a basis, where every row has a pivot symbol that no other row covers. 
+// - The basis is kept across calls, so a call only eliminates rows buffered since the last one, sparsest first. 
+// - Every row that ends up with a single symbol reveals it. 
+// Precoded window: 
+// - Parity symbols are unknowns as well, each parity check is a row that xors to zero. 
+#[derive(Debug, Clone)]
+pub(crate) struct LubyDecoder<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal>
+{
+    // recovered symbols
+    known: HashMap<Symbol, Proposal>,
+    // rows covering at least two unknown symbols, not eliminated yet
+    pending: Vec<Row<Proposal>>,
+    // eliminated rows keyed by their pivot symbol
+    basis: HashMap<Symbol, Row<Proposal>>,
+    // digest of current precoded window
+    digest: Option<u64>,
+    // terms of source symbols in current window
+    window: HashMap<ProposalId, Term>,
+}
+
+// A node in the decoding graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Symbol {
+    // a log entry
+    Source(ProposalId, Term),
+    // a parity symbol of a precoded window, keyed by window digest
+    Parity(u64, usize),
+}
+
+// A linear equation over symbols
+// - no data means the symbols xor to zero
+#[derive(Debug, Clone)]
+struct Row<Proposal> {
+    data: Option<Proposal>,
+    symb: Vec<Symbol>,
+}
+
+impl<Proposal> LubyDecoder<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal> + Clone
+{
+    pub(crate) fn new() -> Self {
+        Self { known: HashMap::new(), pending: vec![], basis: HashMap::new(), digest: None, window: HashMap::new() }
+    }
+    // xor out known symbols
+    fn reduce(&self, Row { mut data, symb }: Row<Proposal>) -> Row<Proposal> {
+        let symb = symb.into_iter().filter(|x| match self.known.get(x) {
+            Some(y) => { data = xor(data.take(), Some(y.clone())); false }
+            None => true
+        }).collect();
+        Row { data, symb }
+    }
+    // propagate degree-1 rows until nothing changes
+    fn peel(&mut self, mut ready: Vec<Row<Proposal>>) {
+        while let Some(row) = ready.pop() {
+            let row = self.reduce(row);
+            match (row.symb.len(), row.data) {
+                (0, _) => continue,
+                // a zero symbol cannot be represented, give up on it
+                (1, None) => continue,
+                (1, Some(data)) => {
+                    let x = row.symb[0];
+                    self.known.insert(x, data);
+                    // rows covering the new symbol may be peeled
+                    let (touched, pending) = std::mem::take(&mut self.pending)
+                        .into_iter().partition(|r| r.symb.contains(&x));
+                    self.pending = pending;
+                    ready.extend(touched);
+                    // so may eliminated rows, they are eliminated again once reduced
+                    let pivots = self.basis.iter().filter(|(_, r)| r.symb.contains(&x)).map(|(p, _)| *p).collect::<Vec<_>>();
+                    ready.extend(pivots.iter().filter_map(|p| self.basis.remove(p)));
+                }
+                (_, data) => self.pending.push(Row { data, symb: row.symb }),
+            }
+        }
+    }
+}
+
+impl<Proposal> CodingDecoder<Proposal> for LubyDecoder<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal> + Clone
+{
+    // get a recovered source symbol
+    fn get(&self, id: ProposalId, term: Term) -> Option<&Proposal> {
+        self.known.get(&Symbol::Source(id, term))
+    }
+    // forget symbols outside the window
+    // - a symbol of an overwritten entry has the same id but another term, so it is forgotten as well
+    // - parity symbols of other windows are forgotten as well
+    // - rows that cover forgotten symbols are dropped
+    fn retain(&mut self, window: &HashMap<ProposalId, Term>) {
+        let digest = self.digest;
+        let keep = |x: &Symbol| match x {
+            Symbol::Source(id, term) => window.get(id) == Some(term),
+            Symbol::Parity(d, _) => Some(*d) == digest,
+        };
+        self.known.retain(|x, _| keep(x));
+        self.pending.retain(|r| r.symb.iter().all(keep));
+        self.basis.retain(|_, r| r.symb.iter().all(keep));
+        self.window = window.clone();
+    }
+    // set up parity checks of a window
+    fn precode(&mut self, window: &[(ProposalId, Term)], precode: Option<Precode>) {
+        let Some(precode) = precode else { self.digest = None; return };
+        let mut hasher = DefaultHasher::new();
+        (window, precode).hash(&mut hasher);
+        let digest = hasher.finish();
+        if self.digest == Some(digest) { return }
+        self.digest = Some(digest);
+        let mut rows = (0..precode.parity(window.len()))
+            .map(|j| Row { data: None, symb: vec![Symbol::Parity(digest, j)] })
+            .collect::<Vec<_>>();
+        for (i, (id, term)) in window.iter().enumerate() {
+            for j in precode.checks(i, window.len()) { rows[j].symb.push(Symbol::Source(*id, *term)) }
+        }
+        self.peel(rows);
+    }
+    // learn a source symbol from elsewhere, e.g. local log
+    fn insert(&mut self, id: ProposalId, term: Term, proposal: Proposal) {
+        if self.known.contains_key(&Symbol::Source(id, term)) { return }
+        self.peel(vec![Row { data: Some(proposal), symb: vec![Symbol::Source(id, term)] }]);
+    }
+    // feed a received codeword
+    // - a codeword with parity symbols is dropped if the window is not precoded
+    // - a codeword with GF(256) coefficients is dropped unless they are all 1
+    // - a codeword over symbols outside the window is dropped
+    fn receive(&mut self, Codeword { data, symb, parity, coef }: Codeword<Proposal>) {
+        if !parity.is_empty() && self.digest.is_none() { return }
+        if coef.iter().any(|c| *c != 1) { return }
+        let Some(symb) = symb.into_iter().map(|id| Some(Symbol::Source(id, *self.window.get(&id)?)))
+            .chain(parity.into_iter().map(|j| Some(Symbol::Parity(self.digest?, j))))
+            .collect() else { return };
+        self.peel(vec![Row { data: Some(data), symb }]);
+    }
+    // eliminate rows buffered since the last call into the basis, when peeling is stuck
+    fn solve(&mut self) {
+        let mut rows = std::mem::take(&mut self.pending);
+        rows.sort_by_key(|r| r.symb.len());
+        for mut row in rows {
+            // xor out the pivots it covers, a basis row covers no other pivot
+            for x in row.symb.clone() {
+                if let Some(other) = self.basis.get(&x) { add(&mut row, other) }
+            }
+            // a row of no symbol is redundant
+            let Some(pivot) = row.symb.first().copied() else { continue };
+            // eliminate the new pivot from other basis rows
+            for other in self.basis.values_mut() {
+                if other.symb.contains(&pivot) { add(other, &row) }
+            }
+            self.basis.insert(pivot, row);
+        }
+        // rows with one symbol are solved, others are kept in the basis
+        let solved = self.basis.iter().filter(|(_, r)| r.symb.len() == 1).map(|(x, _)| *x).collect::<Vec<_>>();
+        let solved = solved.iter().filter_map(|x| self.basis.remove(x)).collect();
+        self.peel(solved);
+    }
+}
+
+// xor a row into another
+fn add<Proposal: BitXor<Proposal, Output = Proposal> + Clone>(row: &mut Row<Proposal>, other: &Row<Proposal>) {
+    for x in other.symb.iter() {
+        match row.symb.iter().position(|y| y == x) {
+            Some(i) => { row.symb.swap_remove(i); }
+            None => row.symb.push(*x),
+        }
+    }
+    row.data = xor(row.data.take(), other.data.clone());
+}
+
+// xor two payloads, where none is zero
+fn xor<Proposal: BitXor<Proposal, Output = Proposal>>(a: Option<Proposal>, b: Option<Proposal>) -> Option<Proposal> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a ^ b),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn peel_chain() {
+        // x0 = 1, x1 = 2, x2 = 4
+        let mut decoder = LubyDecoder::<usize>::new();
+        decoder.retain(&(0..3).map(|i| (ProposalId(i), Term(0))).collect());
+        decoder.receive(Codeword::new(2 ^ 4, vec![ProposalId(1), ProposalId(2)]));
+        decoder.receive(Codeword::new(1 ^ 2, vec![ProposalId(0), ProposalId(1)]));
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), None);
+        assert_eq!(decoder.pending.len(), 2);
+        decoder.receive(Codeword::new(4, vec![ProposalId(2)]));
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), Some(&1));
+        assert_eq!(decoder.get(ProposalId(1), Term(0)), Some(&2));
+        assert_eq!(decoder.get(ProposalId(2), Term(0)), Some(&4));
+        assert_eq!(decoder.pending.len(), 0);
+    }
+
+    #[test]
+    fn solve_stuck() {
+        // x0 = 1, x1 = 2, x2 = 4, no codeword of degree 1
+        let window = (0..3).map(|i| (ProposalId(i), Term(0))).collect::<HashMap<_, _>>();
+        let mut decoder = LubyDecoder::<usize>::new();
+        decoder.retain(&window);
+        decoder.receive(Codeword::new(1 ^ 2, vec![ProposalId(0), ProposalId(1)]));
+        decoder.receive(Codeword::new(2 ^ 4, vec![ProposalId(1), ProposalId(2)]));
+        decoder.receive(Codeword::new(1 ^ 2 ^ 4, vec![ProposalId(0), ProposalId(1), ProposalId(2)]));
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), None);
+        decoder.solve();
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), Some(&1));
+        assert_eq!(decoder.get(ProposalId(1), Term(0)), Some(&2));
+        assert_eq!(decoder.get(ProposalId(2), Term(0)), Some(&4));
+        // rank deficient system stays buffered
+        let mut decoder = LubyDecoder::<usize>::new();
+        decoder.retain(&window);
+        decoder.receive(Codeword::new(1 ^ 2, vec![ProposalId(0), ProposalId(1)]));
+        decoder.receive(Codeword::new(1 ^ 2, vec![ProposalId(0), ProposalId(1)]));
+        decoder.solve();
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), None);
+        assert_eq!(decoder.pending.len(), 0);
+        assert_eq!(decoder.basis.len(), 1);
+    }
+
+    #[test]
+    fn parity_fill() {
+        // x0 = 1, x1 = 2, x2 = 4, x3 = 8, one parity check p0 = x0 ^ x1 ^ x2 ^ x3
+        let window = (0..4).map(|i| (ProposalId(i), Term(0))).collect::<Vec<_>>();
+        let mut decoder = LubyDecoder::<usize>::new();
+        decoder.precode(&window, Some(Precode::new(4, 1).unwrap()));
+        decoder.retain(&window.iter().copied().collect());
+        decoder.receive(Codeword::with_parity(1 ^ 2 ^ 4 ^ 8, vec![], vec![0]));
+        decoder.receive(Codeword::new(1, vec![ProposalId(0)]));
+        decoder.receive(Codeword::with_parity(2 ^ 1 ^ 2 ^ 4 ^ 8, vec![ProposalId(1)], vec![0]));
+        decoder.receive(Codeword::new(4 ^ 8, vec![ProposalId(2), ProposalId(3)]));
+        assert_eq!(decoder.get(ProposalId(3), Term(0)), None);
+        decoder.receive(Codeword::new(4, vec![ProposalId(2)]));
+        assert_eq!(decoder.get(ProposalId(1), Term(0)), Some(&2));
+        assert_eq!(decoder.get(ProposalId(3), Term(0)), Some(&8));
+        // a different window forgets old parity symbols, new ones follow from known sources
+        decoder.precode(&window[1..], Some(Precode::new(4, 1).unwrap()));
+        decoder.retain(&window[1..].iter().copied().collect());
+        assert!(decoder.known.keys().all(|x| !matches!(x, Symbol::Parity(d, _) if Some(*d) != decoder.digest)));
+        assert_eq!(decoder.known.get(&Symbol::Parity(decoder.digest.unwrap(), 0)), Some(&(2 ^ 4 ^ 8)));
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), None);
+    }
+
+    #[test]
+    fn precode_overhead() {
+        // codewords needed for 64 symbols: 68.6 on average without precode, 66.3 with precode
+        let lt = LtScheme::new(DegreeDistribution::robust_soliton(0.1, 0.5).unwrap());
+        let window = (0..64).map(|i| (1usize << i, ProposalId(i as u64), Term(0))).collect::<Vec<_>>();
+        let ids = window.iter().map(|(_, id, term)| (*id, *term)).collect::<Vec<_>>();
+        let need = |precode: Option<Precode>| {
+            let parity = precode.map(|p| p.encode(&window.iter().map(|x| x.0).collect::<Vec<_>>())).unwrap_or_default();
+            let mut total = 0;
+            for _ in 0..500 {
+                let mut decoder = LubyDecoder::<usize>::new();
+                decoder.precode(&ids, precode);
+                decoder.retain(&ids.iter().copied().collect());
+                while ids.iter().any(|(id, term)| decoder.get(*id, *term).is_none()) {
+                    decoder.receive(CodingScheme::<usize>::encode(&lt, &window, &parity).unwrap());
+                    decoder.solve();
+                    total += 1;
+                }
+                assert!(window.iter().all(|(x, id, term)| decoder.get(*id, *term) == Some(x)));
+            }
+            total as f64 / 500.0
+        };
+        let (plain, precoded) = (need(None), need(Some(Precode::new(5, 2).unwrap())));
+        println!("codewords: {plain} without precode, {precoded} with precode");
+        assert!(precoded < plain);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::*;
+use rand::Rng;
+
+// Degree distribution of LT codewords
+// - the distribution is generic over window size k, a table is built for each k
+// - degree d is sampled with probability table[d - 1]
+#[derive(Debug, Clone, PartialEq)]
+pub enum DegreeDistribution {
+    // ρ(1) = 1/k, ρ(d) = 1/(d(d-1))
+    // optimal in expectation, but decoding fails easily in practice
+    IdealSoliton,
+    // ideal soliton with an extra spike at k/R, where R = c ln(k/δ) √k
+    // - larger c means more overhead and fewer decoding failures
+    // - δ bounds the probability of decoding failure
+    RobustSoliton { c: f32, delta: f32 },
+    // user provided weights, weight[d - 1] for degree d
+    // degrees larger than k are truncated
+    Custom(Vec<f32>),
+}
+
+impl DegreeDistribution {
+    pub fn ideal_soliton() -> Self {
+        Self::IdealSoliton
+    }
+    pub fn robust_soliton(c: f32, delta: f32) -> Result<Self, RaftErr> {
+        if !(c > 0.0 && c.is_finite()) { return Err(RaftErr::InvalidDistribution) }
+        if !(delta > 0.0 && delta < 1.0) { return Err(RaftErr::InvalidDistribution) }
+        Ok(Self::RobustSoliton { c, delta })
+    }
+    pub fn custom(weight: Vec<f32>) -> Result<Self, RaftErr> {
+        if weight.iter().any(|x| !(x.is_finite() && *x >= 0.0)) { return Err(RaftErr::InvalidDistribution) }
+        if weight.iter().sum::<f32>() <= 0.0 { return Err(RaftErr::InvalidDistribution) }
+        Ok(Self::Custom(weight))
+    }
+    // probability of degree 1..=k
+    // - the table is normalized, unless it has no mass below k
+    pub fn table(&self, k: usize) -> Vec<f32> {
+        if k == 0 { return vec![] }
+        let kf = k as f32;
+        let ideal = |d: usize| if d == 1 { 1.0 / kf } else { 1.0 / (d * (d - 1)) as f32 };
+        let table = match self {
+            Self::IdealSoliton => (1..=k).map(ideal).collect::<Vec<_>>(),
+            Self::RobustSoliton { c, delta } => {
+                let r = c * (kf / delta).ln() * kf.sqrt();
+                // the spike sits at k/R, but it must be a valid degree
+                let spike = ((kf / r).floor() as usize).clamp(1, k);
+                (1..=k).map(|d| ideal(d) + match d.cmp(&spike) {
+                    std::cmp::Ordering::Less => r / (d as f32 * kf),
+                    std::cmp::Ordering::Equal => r * (r / delta).ln().max(0.0) / kf,
+                    std::cmp::Ordering::Greater => 0.0,
+                }).collect()
+            }
+            Self::Custom(weight) => (0..k).map(|d| weight.get(d).copied().unwrap_or(0.0)).collect(),
+        };
+        let sum = table.iter().sum::<f32>();
+        if sum <= 0.0 { return table }
+        table.into_iter().map(|x| x / sum).collect()
+    }
+    // sample a degree in 1..=k
+    // - if the table has no mass, fall back to degree k
+    pub fn sample(&self, k: usize, rng: &mut impl Rng) -> usize {
+        let table = self.table(k);
+        let r = rng.r#gen::<f32>();
+        let mut s = 0f32;
+        // the last degree absorbs rounding error
+        1 + table.iter().map(|x| {s += *x; s}).position(|x| r < x).unwrap_or(k.saturating_sub(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn soliton_table() {
+        let ideal = DegreeDistribution::ideal_soliton().table(100);
+        assert!((ideal.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!((ideal[1] - 0.5).abs() < 1e-4);
+        let robust = DegreeDistribution::robust_soliton(0.1, 0.05).unwrap().table(100);
+        assert!((robust.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        // robust soliton moves mass towards degree 1
+        assert!(robust[0] > ideal[0]);
+        assert!(DegreeDistribution::robust_soliton(0.1, 1.5).is_err());
+        assert!(DegreeDistribution::custom(vec![0.0, -1.0]).is_err());
+        assert!(DegreeDistribution::custom(vec![]).is_err());
+        // custom table is truncated to the window
+        let custom = DegreeDistribution::custom(vec![1.0, 1.0, 2.0]).unwrap();
+        assert_eq!(custom.table(2), vec![0.5, 0.5]);
+        let mut rng = rand::thread_rng();
+        assert!((0..1000).all(|_| (1..=2).contains(&custom.sample(2, &mut rng))));
+        assert!((0..1000).all(|_| DegreeDistribution::custom(vec![0.0, 1.0]).unwrap().sample(1, &mut rng) == 1));
+    }
+}
@@ -1,32 +1,67 @@
 use std::ops::BitXor;
 
 use crate::raft_nums::*;
+use crate::raft_entry::*;
+use crate::raft_luby_precode::Precode;
+use crate::raft_luby_bytes::CodedBytes;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RaftLubyMsg<Proposal> where
     Proposal: BitXor<Proposal, Output = Proposal>
 {
-    // Proposal request
-    ProposalReq { proposal: Proposal, id: ProposalId },
+    // Proposal request, forwarded from a server to the leader it knows of
+    ProposalReq { proposal: Proposal, id: ProposalId, from: RaftId },
+    // Proposal rejected by a server that is not the leader, with the leader it knows of
+    ProposalRej { id: ProposalId, leader: Option<RaftId> },
     // Replicate a segment of log items
     // Leader should send out this to all
     ReplicateReq {
         commit: usize,
         leader: (Term, RaftId),
         prefix: (Option<Term>, usize),
+        // ids and terms of entries, in log order after prefix
+        // entries other than commands are not coded, and travel inline
+        window: Vec<(ProposalId, Term, Option<Entry<Proposal>>)>,
+        // parity precode applied to the window, if any
+        precode: Option<Precode>,
         patch: Vec<Codeword<Proposal>>,
     },
     // Acknowledge replication
-    ReplicateAck { from: RaftId, sync: usize, tail: usize },
+    // - sync: highest contiguously decoded index
+    // - missing: entries in the window after sync that are not decoded yet
+    ReplicateAck { from: RaftId, sync: usize, missing: Vec<ProposalId> },
     // Reject replication
-    ReplicateRej { from: RaftId, term: Term, at: usize },
+    // - hint: the term of the follower at the prefix and the first index of that term,
+    //   or none and the length of the follower log if it is shorter than the prefix
+    ReplicateRej { from: RaftId, term: Term, at: usize, hint: (Option<Term>, usize) },
+    // Install a snapshot on a follower that lags behind the snapshot point
+    // - last: the snapshot covers entries 0..last.1, and entry last.1 - 1 has term last.0
+    // - config: the configuration in effect at the snapshot point
+    // - size: snapshot size in bytes, split into blocks of given size
+    // - patch: codewords over snapshot blocks, a block is keyed by its index
+    InstallSnapshot {
+        leader: (Term, RaftId),
+        last: (Term, usize),
+        config: Config,
+        size: usize,
+        block: usize,
+        precode: Option<Precode>,
+        patch: Vec<Codeword<CodedBytes>>,
+    },
+    // Pre-vote request, candidate.0 is the term it would start
+    PreVoteReq {
+        candidate: (Term, RaftId),
+        last: (Term, usize),
+    },
+    // Pre-vote acknowledged, a rejection is sent as VoteRej
+    PreVoteAck { from: RaftId, term: Term },
     // Vote request
     VoteReq {
         candidate: (Term, RaftId),
         last: (Term, usize),
     },
     // Vote acknowledged
-    VoteAck { term: Term },
+    VoteAck { from: RaftId, term: Term },
     // Vote rejected
     VoteRej { term: Term }
 }
@@ -35,6 +70,31 @@ pub enum RaftLubyMsg<Proposal> where
 pub struct Codeword<Proposal> where
     Proposal: BitXor<Proposal, Output = Proposal>
 {
-    data: Proposal,
-    symb: Vec<ProposalId>
+    pub(crate) data: Proposal,
+    pub(crate) symb: Vec<ProposalId>,
+    // parity symbols of a precoded window
+    pub(crate) parity: Vec<usize>,
+    // GF(256) coefficients of source symbols, empty means xor
+    pub(crate) coef: Vec<u8>,
+}
+
+impl<Proposal> Codeword<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal>
+{
+    // build a codeword from xor-ed data and the ids of its source symbols
+    pub fn new(data: Proposal, symb: Vec<ProposalId>) -> Self {
+        Self { data, symb, parity: vec![], coef: vec![] }
+    }
+    // build a codeword that also covers parity symbols of a precoded window
+    pub fn with_parity(data: Proposal, symb: Vec<ProposalId>, parity: Vec<usize>) -> Self {
+        Self { data, symb, parity, coef: vec![] }
+    }
+    // build a codeword from a linear combination of source symbols over GF(256)
+    pub fn with_coef(data: Proposal, symb: Vec<ProposalId>, coef: Vec<u8>) -> Self {
+        Self { data, symb, parity: vec![], coef }
+    }
+    // degree of this codeword
+    pub fn degree(&self) -> usize {
+        self.symb.len() + self.parity.len()
+    }
 }
\ No newline at end of file
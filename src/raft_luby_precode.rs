@@ -0,0 +1,66 @@
+use crate::*;
+use std::ops::BitXor;
+
+// Raptor-style parity precode in front of the LT encoder
+// - a window of k source symbols is extended with ⌈k / ratio⌉ parity symbols
+// - the i-th source symbol joins `spread` consecutive parity checks, starting from i * spread
+// - LT codewords are sampled from source and parity symbols together
+// - a follower only needs to recover most symbols, parity checks fill the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Precode {
+    ratio: usize,
+    spread: usize,
+}
+
+impl Precode {
+    pub fn new(ratio: usize, spread: usize) -> Result<Self, RaftErr> {
+        if ratio == 0 || spread == 0 { return Err(RaftErr::InvalidPrecode) }
+        Ok(Self { ratio, spread })
+    }
+    // number of parity symbols for k source symbols
+    pub fn parity(&self, k: usize) -> usize {
+        k.div_ceil(self.ratio)
+    }
+    // parity checks joined by the i-th of k source symbols
+    pub fn checks(&self, i: usize, k: usize) -> impl Iterator<Item = usize> + use<> {
+        let p = self.parity(k);
+        let spread = self.spread;
+        (0..spread.min(p)).map(move |t| (i * spread + t) % p)
+    }
+    // xor source symbols into parity symbols
+    pub(crate) fn encode<Proposal>(&self, source: &[Proposal]) -> Vec<Proposal> where
+        Proposal: BitXor<Proposal, Output = Proposal> + Clone
+    {
+        let mut parity = vec![None::<Proposal>; self.parity(source.len())];
+        for (i, x) in source.iter().enumerate() {
+            for j in self.checks(i, source.len()) {
+                parity[j] = Some(match parity[j].take() { Some(y) => y ^ x.clone(), None => x.clone() });
+            }
+        }
+        // every parity check covers at least one source symbol
+        parity.into_iter().map(|x| x.expect("parity check without source symbol")).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parity_cover() {
+        for (ratio, spread) in [(1, 1), (3, 2), (4, 7), (10, 3)] {
+            let precode = Precode::new(ratio, spread).unwrap();
+            for k in 1..40usize {
+                let source = (0..k).map(|x| 1usize << x).collect::<Vec<_>>();
+                let parity = precode.encode(&source);
+                assert_eq!(parity.len(), k.div_ceil(ratio));
+                // each source symbol shows up in its checks exactly
+                for (i, x) in source.iter().enumerate() {
+                    let checks = precode.checks(i, k).collect::<Vec<_>>();
+                    assert!(parity.iter().enumerate().all(|(j, p)| (p & x != 0) == checks.contains(&j)));
+                }
+            }
+        }
+        assert!(Precode::new(0, 1).is_err());
+    }
+}
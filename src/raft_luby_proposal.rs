@@ -1,5 +1,5 @@
 use crate::*;
-use std::{fmt::Debug, ops::BitXor};
+use std::{collections::HashSet, fmt::Debug, ops::BitXor};
 use serde::{Serialize, Deserialize};
 
 // Lifecycle of a proposal: 
@@ -11,113 +11,308 @@ use serde::{Serialize, Deserialize};
 //    (2.a) The new leader will eventually discard or apply it. 
 // - When the item is committed, reply to the client. 
 // - When the item is discarded, reply to the client. 
+// Both are reported as outcomes to the server the proposal was submitted to, see take_outcomes. 
 impl<Proposal> RaftLubyImpl<Proposal> where
     Proposal: Serialize + for<'de> Deserialize<'de> + Clone + Debug,
     Proposal: BitXor<Proposal, Output = Proposal>
 {
+    // handle a proposal forwarded by another server
+    // - only the leader takes it, the server it was submitted to learns of the leader known here
+    pub(crate) fn handle_proposal(&mut self, proposal: Proposal, id: ProposalId, from: RaftId, adaptor: &impl Adaptor<RaftLubyMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let leader = match self.role {
+            // an earlier attempt in the coding window stands for a retry, its outcome reaches the server as well
+            LubyRole::Leader { .. } => {
+                if self.uncommitted(id, disk) { return }
+                disk.push(Entry::Command(proposal), id, self.term);
+                self.replicate(adaptor, disk);
+                return
+            }
+            LubyRole::Follower { leader } => Some(leader),
+            LubyRole::Candidate { .. } | LubyRole::PreCandidate { .. } => None,
+        };
+        adaptor.send(from, RaftLubyMsg::ProposalRej { id, leader });
+    }
+    // whether an entry with given id is in the uncommitted part of local log
+    // - ids are scanned in place, entries are not copied out of the log
+    pub(crate) fn uncommitted(&self, id: ProposalId, disk: &impl Persistor<Proposal>) -> bool {
+        (self.commitable..disk.last().1).any(|at| disk.id(at) == Some(id))
+    }
+    // raise the commit index, and report proposals committed on the way
+    pub(crate) fn advance(&mut self, commit: usize, disk: &mut impl Persistor<Proposal>) {
+        if commit <= self.commitable { return }
+        self.outcomes.commit(self.commitable..commit, disk);
+        self.commitable = commit;
+        disk.commit(self.commitable);
+    }
     // try replicate based on current knowledge
+    // - codewords are sampled from uncommitted entries
+    // - a follower lagging behind the commit point gets a window from its guessed index
     pub(crate) fn replicate(&mut self, adaptor: &impl Adaptor<RaftLubyMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
-        let LubyRole::Leader { .. } = &self.role else { return };
+        let LubyRole::Leader { matched, guessed, stream, installing } = &self.role else { return };
+        let heartbeat = self.timeout_heart >= self.bound_heart;
         self.timeout_heart = 0;
         println!("RAFT :: {:?} replicate", self.id);
-        for id in self.peers.iter().copied() {
+        let mut update = vec![];
+        let mut installed = vec![];
+        for id in self.membership.config().members() {
             if id == self.id { continue }
-            // let last_index = guessed[&id].min(disk.last().1);
-            // let last_term = last_index.checked_add_signed(-1).map(|x| disk.term(x).unwrap_or(Term(0)));
+            // entries before the snapshot point cannot be sent, send snapshot instead
+            // - a batch of snapshot codewords goes out once per heartbeat, not on every proposal
+            if guessed[&id] < disk.offset().1 {
+                if self.clock - installing[&id] >= self.bound_heart {
+                    self.install(id, adaptor, disk);
+                    installed.push(id);
+                }
+                continue
+            }
+            // until an entry of this term commits, a window from the commit point may never reach one,
+            // so it starts from what the follower matched instead
+            let anchor = if disk.term(self.commitable) == Some(self.term) { self.commitable } else { self.commitable.max(matched[&id]) };
+            let last_index = guessed[&id].min(anchor).min(disk.last().1).max(disk.offset().1);
+            let last_term = last_index.checked_add_signed(-1).map(|x| disk.term(x).unwrap_or(Term(0)));
+            let (window, precode, patch) = match &stream[&id] {
+                LubyStream::Coded { window } => {
+                    let window = disk.slice(last_index..last_index+window);
+                    let coded = commands(&window);
+                    let parity = self.precode.map(|p| p.encode(&coded.iter().map(|x| x.0.clone()).collect::<Vec<_>>())).unwrap_or_default();
+                    let patch = (0..self.batch).filter_map(|_| self.scheme.encode(&coded, &parity)).collect::<Vec<_>>();
+                    (window, self.precode, patch)
+                }
+                LubyStream::Systematic { missing } => {
+                    let window = disk.slice(last_index..last_index+self.batch);
+                    let patch = commands(&window).into_iter()
+                        .filter(|(_, id, _)| missing.is_empty() || missing.contains(id))
+                        .map(|(proposal, id, _)| Codeword::new(proposal, vec![id]))
+                        .collect::<Vec<_>>();
+                    (window, None, patch)
+                }
+                LubyStream::Source { sent, lost } => {
+                    let window = disk.slice(last_index..last_index+self.batch);
+                    let sent = (*sent).clamp(last_index, last_index + window.len());
+                    let acked = matched[&id].clamp(last_index, sent);
+                    // without any ack since last heartbeat, assume everything unacked is lost
+                    let waiting = heartbeat || lost.is_none();
+                    let lost = match lost {
+                        None if heartbeat => window[acked - last_index..sent - last_index].to_vec(),
+                        None => vec![],
+                        Some(lost) => window[..sent - last_index].iter().filter(|(_, id, _)| lost.contains(id)).cloned().collect(),
+                    };
+                    // repair codewords only cover lost commands, one extra codeword tolerates a bit more loss
+                    let repair = if lost.is_empty() { 0 } else { lost.len() + 1 };
+                    let lost = commands(&lost);
+                    let patch = (0..repair).filter_map(|_| self.scheme.encode(&lost, &[]))
+                        .chain(commands(&window[sent - last_index..]).into_iter()
+                            .map(|(proposal, id, _)| Codeword::new(proposal, vec![id])))
+                        .collect::<Vec<_>>();
+                    // a heartbeat starts waiting for acks again, reported loss is repaired by now
+                    let lost = if waiting { None } else { Some(vec![]) };
+                    update.push((id, LubyStream::Source { sent: last_index + window.len(), lost }));
+                    (window, None, patch)
+                }
+            };
             adaptor.send(id, RaftLubyMsg::ReplicateReq {
-                patch: (0..self.batch).map(|_| self.encode(disk)).collect::<Vec<_>>(), 
+                patch,
                 leader: (self.term, self.id), 
                 commit: self.commitable,
-                prefix: todo!()
+                prefix: (last_term, last_index),
+                window: window.iter().map(|(entry, id, term)| (*id, *term, match entry {
+                    Entry::Command(_) => None,
+                    entry => Some(entry.clone()),
+                })).collect(),
+                precode,
             });
         }
+        let LubyRole::Leader { stream, installing, .. } = &mut self.role else { return };
+        stream.extend(update);
+        for id in installed { installing.insert(id, self.clock); }
     }
-    // encode a codeword from disk
-    pub(crate) fn encode(&mut self, disk: &mut impl Persistor<Proposal>) -> Codeword<Proposal> {
-        // random number
-        let r = rand::random::<f32>();
-        let mut s = 0f32;
-        // select degree
-        let d = 1 + self.degdist.iter().map(|x| {s += *x; s}).enumerate().find(|(i, x)| *x < r).unwrap().0;
-        // sample elements
-        disk.slice(self.commitable..disk.last().1);
-        // 
-        todo!()
-        // // how to sample
-        // Codeword::sample(data, d)
-    }
-    // validate and append delta
+    // validate, decode and append delta
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn handle_replicate(&mut self,
         (leader_term, leader_id): (Term, RaftId),
         (prefix_term, prefix_index): (Option<Term>, usize),
-        patch: Vec<(Proposal, ProposalId, Term)>,
+        window: Vec<(ProposalId, Term, Option<Entry<Proposal>>)>,
+        precode: Option<Precode>,
+        patch: Vec<Codeword<Proposal>>,
         commit: usize,
         adaptor: &impl Adaptor<RaftLubyMsg<Proposal>>,
         disk: &mut impl Persistor<Proposal>
     ) {
         // if term is outdated or log doesn't match, reply append failed
-        let reject = RaftLubyMsg::ReplicateRej {
-            from: self.id, 
-            term: self.term,
-            at: prefix_index
-        };
+        // - on a mismatch, the hint lets the leader skip the whole conflicting term at once
+        let (id, term) = (self.id, self.term);
+        let reject = |hint| RaftLubyMsg::ReplicateRej { from: id, term, at: prefix_index, hint };
         if leader_term < self.term {
             println!("RAFT :: {:?} :: reject replication because current term is bigger", self.id);
-            adaptor.send(leader_id, reject.clone());
+            adaptor.send(leader_id, reject((None, prefix_index)));
             return;
         }
         // if currently i'm not a follower in this term, convert to follower
+        // - the timer restarts within the first half of its bound, so that a follower
+        //   never times out right after hearing from the leader
         self.role = LubyRole::Follower { leader: leader_id };
-        self.timeout_elect = rand::random::<u64>() % self.bound_elect;
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
         if self.term < leader_term {
             self.term = leader_term;
             self.vote = None;
             disk.persist(self.term, self.vote);
         }
-        if prefix_term.is_some() && prefix_term != disk.term(prefix_index.checked_add_signed(-1).unwrap_or(0)) {
+        // entries covered by local snapshot are committed, so they always match
+        if prefix_term.is_some() && prefix_index >= disk.offset().1 && prefix_term != disk.term(prefix_index.checked_add_signed(-1).unwrap_or(0)) {
             println!("RAFT :: {:?} :: reject replication because prefix doesn't match", self.id);
-            adaptor.send(leader_id, reject);
+            adaptor.send(leader_id, reject(conflict(prefix_index, disk)));
             return;
         }
-        // modify or update replicated entries
+        // only keep decoding state for coded entries of the current window
+        let coded = window.iter().filter(|x| x.2.is_none()).map(|(id, term, _)| (*id, *term)).collect::<Vec<_>>();
+        self.buff.precode(&coded, precode);
+        self.buff.retain(&coded.iter().copied().collect());
+        // skip entries covered by local snapshot
+        let skip = disk.offset().1.saturating_sub(prefix_index).min(window.len());
+        let (prefix_index, window) = (prefix_index + skip, &window[skip..]);
+        // entries already in local log are known symbols
+        // - an uncommitted entry may be overwritten under the same id, so the term must match as well
+        for ((entry, id, term), (expect, expect_term, _)) in disk.slice(prefix_index..prefix_index + window.len()).into_iter().zip(window.iter()) {
+            if let Entry::Command(proposal) = entry && id == *expect && term == *expect_term { self.buff.insert(id, term, proposal) }
+        }
+        // peel received codewords, then solve what peeling cannot
+        for codeword in patch { self.buff.receive(codeword) }
+        self.buff.solve();
+        // modify or update decoded entries, stop at the first hole
         // get the last synchronized entry
+        let patch = window.iter()
+            .map_while(|(id, term, inline)| match inline {
+                Some(entry) => Some((entry.clone(), *id, *term)),
+                None => self.buff.get(*id, *term).map(|x| (Entry::Command(x.clone()), *id, *term)),
+            })
+            .collect::<Vec<_>>();
         let sync = disk.append(prefix_index, patch);
+        self.outcomes.discard(disk.discarded());
+        self.membership.scan(prefix_index, disk);
         // update commitable index
-        if commit >= self.commitable {
-            self.commitable = commit.min(disk.last().1);
-            disk.commit(commit.min(disk.last().1));
-        }
-        adaptor.send(leader_id, RaftLubyMsg::ReplicateAck { from: self.id, tail: disk.last().1, sync });
+        // - a short patch after a back off never lowers it
+        self.advance(commit.min(sync), disk);
+        // report undecoded entries after the synchronized prefix
+        let missing = window.iter().skip(sync.saturating_sub(prefix_index))
+            .filter(|(id, term, inline)| inline.is_none() && self.buff.get(*id, *term).is_none())
+            .map(|(id, _, _)| *id).collect();
+        adaptor.send(leader_id, RaftLubyMsg::ReplicateAck { from: self.id, sync, missing });
     }
     // handle follower/candidate acknowledge
+    // - a follower behind the commit point gets systematic sends
+    // - a follower that decoded everything gets a wider coding window
+    // - a follower that makes no progress gets a narrower coding window, then systematic sends
+    // - a follower that gets source symbols reports loss, which is covered by repair codewords
     pub(crate) fn handle_replicate_ack(&mut self,
         from: RaftId,
         sync: usize,
-        tail: usize,
+        missing: Vec<ProposalId>,
         disk: &mut impl Persistor<Proposal>
     ) {
-        let LubyRole::Leader { matched } = &mut self.role else { return };
+        let fresh = self.stream(sync);
+        let LubyRole::Leader { matched, guessed, stream, .. } = &mut self.role else { return };
+        // servers outside the configuration are not tracked
+        if !matched.contains_key(&from) { return }
+        let progress = sync > matched[&from];
+        *guessed.get_mut(&from).expect("every peer should be logged") = sync;
         *matched.get_mut(&from).expect("every peer should be logged") = sync;
-        let mut matches = matched.values().copied().collect::<Vec<_>>();
-        matches.sort();
-        self.commitable = self.commitable.max(matches[self.peers.len() / 2 - 1]);
-        disk.commit(self.commitable);
+        let stream = stream.get_mut(&from).expect("every peer should be logged");
+        *stream = match stream {
+            _ if sync < self.commitable => LubyStream::Systematic { missing },
+            LubyStream::Source { sent, .. } => LubyStream::Source { sent: *sent, lost: Some(missing) },
+            LubyStream::Coded { window } if missing.is_empty() => LubyStream::Coded { window: (*window * 2).min(self.batch) },
+            LubyStream::Systematic { .. } if missing.is_empty() => fresh,
+            LubyStream::Coded { window } if progress => LubyStream::Coded { window: *window },
+            LubyStream::Coded { window } if *window > 1 => LubyStream::Coded { window: *window / 2 },
+            _ => LubyStream::Systematic { missing },
+        };
+        // learners are tracked as well, but only voters count toward the commit index
+        // - only an entry of the current term is committed by counting replicas, earlier entries follow it,
+        //   otherwise an entry on a majority can still be overwritten by a later leader (figure 8 of the paper)
+        let last = disk.last().1;
+        let committed = self.membership.config().committed(|x| if x == self.id { last } else { matched.get(&x).copied().unwrap_or(0) });
+        if committed > 0 && disk.term(committed - 1) == Some(self.term) { self.advance(committed, disk) }
+        self.reconfigured(disk);
+    }
+    // push a configuration entry as the leader, and track new members
+    pub(crate) fn configure(&mut self, config: Config, id: ProposalId, disk: &mut impl Persistor<Proposal>) {
+        let fresh = self.stream(0);
+        let LubyRole::Leader { matched, guessed, stream, installing } = &mut self.role else { return };
+        for x in config.members() {
+            matched.entry(x).or_insert(0);
+            guessed.entry(x).or_insert(disk.last().1);
+            stream.entry(x).or_insert(fresh.clone());
+            installing.entry(x).or_insert(0);
+        }
+        self.membership.push(disk.last().1, config.clone());
+        disk.push(Entry::Config(config), id, self.term);
+    }
+    // move on once the configuration in effect is committed
+    // - joint consensus C_old,new is followed by C_new
+    // - a leader outside C_new steps down
+    pub(crate) fn reconfigured(&mut self, disk: &mut impl Persistor<Proposal>) {
+        let Some(at) = self.membership.index() else { return };
+        if at >= self.commitable { return }
+        let config = self.membership.config().clone();
+        if config.joint.is_some() {
+            // the outcome of the change is reported for C_old,new, C_new gets an id of its own
+            self.configure(config.leave(), ProposalId::leave(at), disk);
+        } else if !config.is_voter(self.id) {
+            println!("RAFT :: {:?} step down, removed from configuration", self.id);
+            self.role = LubyRole::Candidate { votes: HashSet::new() };
+        }
     }
     // handle follower/candidate rejection
     pub(crate) fn handle_replicate_rej(&mut self,
         from: RaftId,
         term: Term,
-        at: usize, disk: &mut impl Persistor<Proposal>
+        at: usize,
+        hint: (Option<Term>, usize),
+        disk: &mut impl Persistor<Proposal>
     ) {
-        let LubyRole::Leader { .. } = &mut self.role else { return };
+        let LubyRole::Leader { guessed, stream, .. } = &mut self.role else { return };
+        // servers outside the configuration are not tracked
+        if !guessed.contains_key(&from) { return }
         if term <= self.term {
-            todo!()
-            // *guessed.get_mut(&from).expect("every peer should be logged") = at / 2;
+            // the follower lags behind, back off and send entries as they are
+            *guessed.get_mut(&from).expect("every peer should be logged") = backtrack(at, hint, disk);
+            *stream.get_mut(&from).expect("every peer should be logged") = LubyStream::Systematic { missing: vec![] };
         } else {
-            self.role = LubyRole::Candidate { count: 0 };
+            self.role = LubyRole::Candidate { votes: HashSet::new() };
             self.term = term;
             self.vote = None;
             disk.persist(self.term, self.vote);
         }
     }
-}
\ No newline at end of file
+}
+
+// commands of a window, which are the coded entries
+fn commands<Proposal: Clone>(window: &[(Entry<Proposal>, ProposalId, Term)]) -> Vec<(Proposal, ProposalId, Term)> {
+    window.iter().filter_map(|(entry, id, term)| match entry {
+        Entry::Command(proposal) => Some((proposal.clone(), *id, *term)),
+        _ => None,
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    #[test]
+    fn overwrite_same_id() {
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<RaftLubyMsg<usize>>::new(2)));
+        let adaptor = MockAdaptor::new(RaftId(1), network.clone());
+        let mut disk = MockPersistor::<usize>::new();
+        disk.push(Entry::Command(1), ProposalId(0), Term(1));
+        disk.push(Entry::Command(2), ProposalId(1), Term(1));
+        let mut node = RaftLubyImpl::new(RaftId(1), 10, vec![RaftId(0)], DegreeDistribution::robust_soliton(0.1, 0.5).unwrap(), 100, 2, &mut disk);
+        // the leader of term 2 overwrote the uncommitted entry at 1 with another payload under the same id
+        let leader = [(Entry::Command(1), ProposalId(0), Term(1)), (Entry::Command(5), ProposalId(1), Term(2))];
+        node.handle_replicate((Term(2), RaftId(0)), (Some(Term(1)), 1), vec![(ProposalId(1), Term(2), None)], None, vec![], 0, &adaptor, &mut disk);
+        // the stale payload is not taken for the new entry, which waits for a codeword
+        assert_eq!(disk.term(1), Some(Term(1)));
+        node.handle_replicate((Term(2), RaftId(0)), (Some(Term(1)), 1), vec![(ProposalId(1), Term(2), None)], None, vec![Codeword::new(5, vec![ProposalId(1)])], 0, &adaptor, &mut disk);
+        assert_eq!(disk.slice(0..2), leader);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::*;
+use rand::Rng;
+use std::{collections::HashMap, ops::BitXor};
+
+// A symbol that can be scaled by an element of GF(256)
+// - addition is xor
+// - scaling multiplies every byte, so scaling distributes over xor
+pub trait Galois: BitXor<Self, Output = Self> + Clone + Sized {
+    fn scale(&self, c: u8) -> Self;
+}
+
+macro_rules! galois_int {
+    ($($t:ty),*) => {$(
+        impl Galois for $t {
+            fn scale(&self, c: u8) -> Self {
+                <$t>::from_le_bytes(self.to_le_bytes().map(|x| gf_mul(x, c)))
+            }
+        }
+    )*};
+}
+
+galois_int!(u8, u16, u32, u64, u128, usize);
+
+// multiply in GF(256) with polynomial x^8 + x^4 + x^3 + x^2 + 1
+pub(crate) fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut p = 0u8;
+    while b != 0 {
+        if b & 1 != 0 { p ^= a; }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry { a ^= 0x1d; }
+        b >>= 1;
+    }
+    p
+}
+
+// inverse in GF(256), a^254 = a^-1
+pub(crate) fn gf_inv(a: u8) -> u8 {
+    assert!(a != 0, "zero has no inverse");
+    let mut r = 1u8;
+    for _ in 0..254 { r = gf_mul(r, a); }
+    r
+}
+
+// Random linear network coding over GF(256)
+// - every codeword covers the whole window, with a random non-zero coefficient per symbol
+// - any k codewords of a k-symbol window are almost surely independent
+// - precode is not used, parity symbols are ignored
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RlncScheme;
+
+impl RlncScheme {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<Proposal> CodingScheme<Proposal> for RlncScheme where
+    Proposal: Galois + 'static
+{
+    fn encode(&self, window: &[(Proposal, ProposalId, Term)], _parity: &[Proposal]) -> Option<Codeword<Proposal>> {
+        let mut rng = rand::thread_rng();
+        let coef = window.iter().map(|_| rng.gen_range(1..=255u8)).collect::<Vec<_>>();
+        let data = window.iter().zip(coef.iter()).map(|(x, c)| x.0.scale(*c)).reduce(|a, b| a ^ b)?;
+        let symb = window.iter().map(|x| x.1).collect();
+        Some(Codeword::with_coef(data, symb, coef))
+    }
+    fn decoder(&self) -> Box<dyn CodingDecoder<Proposal>> {
+        Box::new(RlncDecoder::new())
+    }
+}
+
+// A source symbol, keyed by id and term
+type Column = (ProposalId, Term);
+
+// Gaussian elimination decoder over GF(256)
+#[derive(Debug, Clone)]
+pub(crate) struct RlncDecoder<Proposal> {
+    // recovered source symbols, keyed by id and term
+    known: HashMap<Column, Proposal>,
+    // rows with their coefficients
+    pending: Vec<(Proposal, Vec<(Column, u8)>)>,
+    // terms of source symbols in current window
+    window: HashMap<ProposalId, Term>,
+}
+
+impl<Proposal: Galois> RlncDecoder<Proposal> {
+    pub(crate) fn new() -> Self {
+        Self { known: HashMap::new(), pending: vec![], window: HashMap::new() }
+    }
+}
+
+impl<Proposal: Galois> CodingDecoder<Proposal> for RlncDecoder<Proposal> {
+    fn get(&self, id: ProposalId, term: Term) -> Option<&Proposal> {
+        self.known.get(&(id, term))
+    }
+    fn retain(&mut self, window: &HashMap<ProposalId, Term>) {
+        let keep = |(id, term): &Column| window.get(id) == Some(term);
+        self.known.retain(|x, _| keep(x));
+        self.pending.retain(|(_, row)| row.iter().all(|(x, _)| keep(x)));
+        self.window = window.clone();
+    }
+    fn precode(&mut self, _window: &[(ProposalId, Term)], _precode: Option<Precode>) {}
+    fn insert(&mut self, id: ProposalId, term: Term, proposal: Proposal) {
+        self.known.entry((id, term)).or_insert(proposal);
+    }
+    // codewords without coefficients are xor codewords
+    // - a codeword over symbols outside the window is dropped
+    fn receive(&mut self, Codeword { data, symb, parity, coef }: Codeword<Proposal>) {
+        if !parity.is_empty() { return }
+        let coef = if coef.is_empty() { vec![1; symb.len()] } else { coef };
+        let Some(row) = symb.into_iter().zip(coef).map(|(id, c)| Some(((id, *self.window.get(&id)?), c))).collect() else { return };
+        self.pending.push((data, row));
+    }
+    fn solve(&mut self) {
+        // reduce every row by known symbols
+        let mut rows = std::mem::take(&mut self.pending).into_iter().map(|(mut data, row)| {
+            let row = row.into_iter().filter(|(id, c)| match self.known.get(id) {
+                Some(x) => { data = data.clone() ^ x.scale(*c); false }
+                None => *c != 0
+            }).collect::<Vec<_>>();
+            (data, row)
+        }).filter(|(_, row)| !row.is_empty()).collect::<Vec<_>>();
+        // gauss-jordan elimination, one pivot column per row
+        let mut pivot = 0;
+        while pivot < rows.len() {
+            // normalize pivot row, so that its first coefficient is 1
+            let Some((col, c)) = rows[pivot].1.first().copied() else { rows.swap_remove(pivot); continue };
+            let inv = gf_inv(c);
+            rows[pivot].0 = rows[pivot].0.scale(inv);
+            for (_, x) in rows[pivot].1.iter_mut() { *x = gf_mul(*x, inv); }
+            let (data, row) = rows[pivot].clone();
+            // eliminate pivot column from other rows
+            for (i, (other, coef)) in rows.iter_mut().enumerate() {
+                if i == pivot { continue }
+                let Some(f) = coef.iter().find(|(id, _)| *id == col).map(|(_, f)| *f) else { continue };
+                *other = other.clone() ^ data.scale(f);
+                for (id, x) in row.iter() {
+                    match coef.iter_mut().find(|(y, _)| y == id) {
+                        Some((_, y)) => *y ^= gf_mul(*x, f),
+                        None => coef.push((*id, gf_mul(*x, f))),
+                    }
+                }
+                coef.retain(|(_, y)| *y != 0);
+            }
+            pivot += 1;
+        }
+        // rows with a single column reveal a symbol
+        for (data, row) in rows {
+            match row.as_slice() {
+                [] => continue,
+                [(id, c)] => { self.known.insert(*id, data.scale(gf_inv(*c))); }
+                _ => self.pending.push((data, row)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gf_field() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+            assert_eq!(gf_mul(a, 1), a);
+        }
+        assert_eq!(0x1234usize.scale(1), 0x1234);
+    }
+
+    #[test]
+    fn rlnc_decode() {
+        let window = (0..8).map(|i| (1usize << (i * 7), ProposalId(i), Term(0))).collect::<Vec<_>>();
+        let mut decoder = CodingScheme::<usize>::decoder(&RlncScheme::new());
+        decoder.retain(&window.iter().map(|(_, id, term)| (*id, *term)).collect());
+        // a codeword per symbol is almost always enough
+        for _ in 0..window.len() + 2 { decoder.receive(RlncScheme::new().encode(&window, &[]).unwrap()); }
+        decoder.solve();
+        for (x, id, term) in window.iter() { assert_eq!(decoder.get(*id, *term), Some(x)); }
+    }
+
+    #[test]
+    fn rlnc_exact() {
+        // x0 = 1, x1 = 2, x2 = 4, x3 = 8, each combination covers one more symbol than the last
+        let window = (0..4).map(|i| (ProposalId(i), Term(0))).collect::<Vec<_>>();
+        let source = [1usize, 2, 4, 8];
+        let mut decoder = RlncDecoder::<usize>::new();
+        decoder.retain(&window.iter().copied().collect());
+        for k in 1..=4 {
+            let coef = (0..k).map(|i| (3 + i * 5) as u8).collect::<Vec<_>>();
+            let data = source[..k].iter().zip(coef.iter()).map(|(x, c)| x.scale(*c)).reduce(|a, b| a ^ b).unwrap();
+            decoder.receive(Codeword::with_coef(data, window[..k].iter().map(|(id, _)| *id).collect(), coef));
+        }
+        decoder.solve();
+        for ((id, term), x) in window.iter().zip(source.iter()) { assert_eq!(decoder.get(*id, *term), Some(x)); }
+        assert!(decoder.pending.is_empty());
+        // one combination short, x2 and x3 are pinned down, but x0 and x1 only share a single equation
+        let mut decoder = RlncDecoder::<usize>::new();
+        decoder.retain(&window.iter().copied().collect());
+        for k in [2, 3, 4] {
+            let coef = vec![7; k];
+            let data = source[..k].iter().map(|x| x.scale(7)).reduce(|a, b| a ^ b).unwrap();
+            decoder.receive(Codeword::with_coef(data, window[..k].iter().map(|(id, _)| *id).collect(), coef));
+        }
+        decoder.solve();
+        assert_eq!(decoder.get(ProposalId(0), Term(0)), None);
+        assert_eq!(decoder.get(ProposalId(1), Term(0)), None);
+        assert_eq!(decoder.get(ProposalId(2), Term(0)), Some(&4));
+        assert_eq!(decoder.get(ProposalId(3), Term(0)), Some(&8));
+    }
+}
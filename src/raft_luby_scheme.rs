@@ -0,0 +1,72 @@
+use crate::*;
+use std::{collections::HashMap, ops::BitXor};
+
+// Coding backend of luby raft
+// - the leader encodes codewords from a window of log entries
+// - followers decode codewords with a decoder provided by the same scheme
+// - every server in a cluster must use the same scheme
+pub trait CodingScheme<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal>
+{
+    // encode a codeword from source symbols and parity symbols of the window
+    // - return none if the window is empty
+    fn encode(&self, window: &[(Proposal, ProposalId, Term)], parity: &[Proposal]) -> Option<Codeword<Proposal>>;
+    // a fresh decoder for a follower
+    fn decoder(&self) -> Box<dyn CodingDecoder<Proposal>>;
+}
+
+// Stateful decoder kept by a follower
+pub trait CodingDecoder<Proposal> where
+    Proposal: BitXor<Proposal, Output = Proposal>
+{
+    // get a recovered source symbol
+    // - a symbol is keyed by id and term, since an uncommitted entry may be overwritten under the same id
+    fn get(&self, id: ProposalId, term: Term) -> Option<&Proposal>;
+    // forget symbols outside the window, codewords are resolved against it until the next call
+    fn retain(&mut self, window: &HashMap<ProposalId, Term>);
+    // set up parity checks of a window
+    fn precode(&mut self, window: &[(ProposalId, Term)], precode: Option<Precode>);
+    // learn a source symbol from elsewhere, e.g. local log
+    fn insert(&mut self, id: ProposalId, term: Term, proposal: Proposal);
+    // feed a received codeword
+    fn receive(&mut self, codeword: Codeword<Proposal>);
+    // recover as many symbols as possible from buffered codewords
+    fn solve(&mut self);
+}
+
+// Luby transform codes over xor
+#[derive(Debug, Clone, PartialEq)]
+pub struct LtScheme {
+    degdist: DegreeDistribution,
+}
+
+impl LtScheme {
+    pub fn new(degdist: DegreeDistribution) -> Self {
+        Self { degdist }
+    }
+}
+
+impl<Proposal> CodingScheme<Proposal> for LtScheme where
+    Proposal: BitXor<Proposal, Output = Proposal> + Clone + 'static
+{
+    fn encode(&self, window: &[(Proposal, ProposalId, Term)], parity: &[Proposal]) -> Option<Codeword<Proposal>> {
+        if window.is_empty() { return None }
+        let mut rng = rand::thread_rng();
+        // select degree over source and parity symbols
+        let k = window.len() + parity.len();
+        let d = self.degdist.sample(k, &mut rng);
+        // sample d distinct elements, keep them in log order
+        let mut pick = rand::seq::index::sample(&mut rng, k, d).into_vec();
+        pick.sort();
+        // xor sampled payloads together
+        let data = pick.iter()
+            .map(|i| window.get(*i).map(|x| x.0.clone()).unwrap_or_else(|| parity[*i - window.len()].clone()))
+            .reduce(|a, b| a ^ b)?;
+        let symb = pick.iter().filter_map(|i| window.get(*i).map(|x| x.1)).collect::<Vec<_>>();
+        let parity = pick.iter().filter(|i| **i >= window.len()).map(|i| *i - window.len()).collect::<Vec<_>>();
+        Some(Codeword::with_parity(data, symb, parity))
+    }
+    fn decoder(&self) -> Box<dyn CodingDecoder<Proposal>> {
+        Box::new(LubyDecoder::new())
+    }
+}
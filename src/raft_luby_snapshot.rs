@@ -0,0 +1,93 @@
+use crate::*;
+use serde::{Serialize, Deserialize};
+use std::{fmt::Debug, ops::BitXor};
+
+// A snapshot in transfer: last term and index, size in bytes and block size
+pub(crate) type SnapshotPoint = (Term, usize, usize, usize);
+
+// Fountain coded snapshot transfer to a follower that lags behind the snapshot point: 
+// - The snapshot is split into fixed-size blocks, and the leader keeps sending fresh codewords over them. 
+// - The follower decodes blocks as soon as it has enough codewords, a lost codeword is never resent. 
+// - A partial decoder survives leader changes, since a snapshot at the same point is the same state. 
+// - Once every block is decoded, the follower compacts its log and acknowledges replication. 
+impl<Proposal> RaftLubyImpl<Proposal> where
+    Proposal: Serialize + for<'de> Deserialize<'de> + Clone + Debug,
+    Proposal: BitXor<Proposal, Output = Proposal>
+{
+    // split a snapshot into blocks, keyed by block index
+    fn blocks(snapshot: &[u8], block: usize, term: Term) -> Vec<(CodedBytes, ProposalId, Term)> {
+        snapshot.chunks(block).enumerate()
+            .map(|(i, x)| (CodedBytes::new(x), ProposalId(i as u64), term))
+            .collect()
+    }
+    // send fresh snapshot codewords to a follower
+    pub(crate) fn install(&self, id: RaftId, adaptor: &impl Adaptor<RaftLubyMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let Some(snapshot) = disk.snapshot() else { return };
+        let last = disk.offset();
+        let window = Self::blocks(&snapshot, self.block, last.0);
+        let parity = self.precode.map(|p| p.encode(&window.iter().map(|x| x.0.clone()).collect::<Vec<_>>())).unwrap_or_default();
+        adaptor.send(id, RaftLubyMsg::InstallSnapshot {
+            leader: (self.term, self.id),
+            size: snapshot.len(),
+            block: self.block,
+            precode: self.precode,
+            patch: (0..self.batch).filter_map(|_| self.blockcode.encode(&window, &parity)).collect(),
+            config: self.membership.base().clone(),
+            last,
+        });
+    }
+    // receive snapshot codewords
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn handle_install_snapshot(&mut self,
+        (leader_term, leader_id): (Term, RaftId),
+        ((last_term, last_index), config): ((Term, usize), Config),
+        (size, block): (usize, usize),
+        precode: Option<Precode>,
+        patch: Vec<Codeword<CodedBytes>>,
+        adaptor: &impl Adaptor<RaftLubyMsg<Proposal>>,
+        disk: &mut impl Persistor<Proposal>
+    ) {
+        if leader_term < self.term {
+            println!("RAFT :: {:?} :: reject snapshot because current term is bigger", self.id);
+            adaptor.send(leader_id, RaftLubyMsg::ReplicateRej { from: self.id, term: self.term, at: last_index, hint: (None, last_index) });
+            return;
+        }
+        // if currently i'm not a follower in this term, convert to follower
+        self.role = LubyRole::Follower { leader: leader_id };
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+        if self.term < leader_term {
+            self.term = leader_term;
+            self.vote = None;
+            disk.persist(self.term, self.vote);
+        }
+        // committed entries are never lost, nothing to install
+        if last_index <= self.commitable {
+            self.receiving = None;
+            adaptor.send(leader_id, RaftLubyMsg::ReplicateAck { from: self.id, sync: self.commitable, missing: vec![] });
+            return;
+        }
+        // start over on a different snapshot
+        let point = (last_term, last_index, size, block);
+        if self.receiving.as_ref().is_none_or(|(x, _)| *x != point) {
+            self.receiving = Some((point, LubyDecoder::new()));
+        }
+        let Some((_, decoder)) = &mut self.receiving else { unreachable!() };
+        let window = (0..size.div_ceil(block)).map(|i| (ProposalId(i as u64), last_term)).collect::<Vec<_>>();
+        decoder.precode(&window, precode);
+        decoder.retain(&window.iter().copied().collect());
+        for codeword in patch { decoder.receive(codeword) }
+        decoder.solve();
+        // wait for more codewords
+        let Some(blocks) = window.iter().map(|(id, term)| decoder.get(*id, *term).map(|x| x.to_vec())).collect::<Option<Vec<_>>>() else { return };
+        // the whole snapshot is decoded, install it
+        self.receiving = None;
+        println!("RAFT :: {:?} :: install snapshot at {last_index:?}", self.id);
+        // proposals in local log are committed if it matches the snapshot point, otherwise they are dropped
+        if disk.term(last_index - 1) == Some(last_term) { self.advance(last_index, disk) }
+        disk.compact(last_index, last_term, blocks.concat());
+        self.outcomes.discard(disk.discarded());
+        self.membership.install(config, disk);
+        self.advance(last_index, disk);
+        adaptor.send(leader_id, RaftLubyMsg::ReplicateAck { from: self.id, sync: last_index, missing: vec![] });
+    }
+}
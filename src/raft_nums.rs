@@ -1,7 +1,7 @@
 //! Various 'numbers' used as id,term,log entry
 use std::ops::Add;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Term(pub(crate) u64);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -10,6 +10,32 @@ pub struct RaftId(pub(crate) u64);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ProposalId(pub(crate) u64);
 
+// A proposal id of a client session
+// - the upper half is the client id, the lower half is the sequence number of the command
+// - client 0 stands for no session, so plain ids below 2^32 are never deduplicated
+// - clients from 2^31 on are reserved for entries a leader pushes on its own
+impl ProposalId {
+    // the id of the no-op a leader pushes when elected, named after its term
+    pub(crate) fn noop(term: Term) -> Self {
+        ProposalId(3 << 62 | term.0)
+    }
+    // the id of C_new that ends a membership change, named after the index of its C_old,new
+    pub(crate) fn leave(at: usize) -> Self {
+        ProposalId(2 << 62 | at as u64)
+    }
+    // panics on a reserved client id
+    pub fn session(client: u32, seq: u32) -> Self {
+        assert!(client < 1 << 31, "clients from 2^31 on are reserved");
+        ProposalId(((client as u64) << 32) | seq as u64)
+    }
+    pub fn client(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+    pub fn seq(&self) -> u32 {
+        self.0 as u32
+    }
+}
+
 impl Add<u64> for Term {
     type Output = Self;
     fn add(self, rhs: u64) -> Self::Output {
@@ -22,5 +48,45 @@ pub enum RaftErr {
     // Proposal Failed: 
     // 1. The proposer doesn't know who is the leader of current term. 
     // 2. A log entry that contains the proposal is overwritten. 
+    // 3. An earlier attempt with the same id is still uncommitted, in Luby mode. 
     ProposalFailed { id: ProposalId },
+    // Invalid degree distribution: 
+    // 1. Parameters of robust soliton are out of range. 
+    // 2. A custom table has negative weights or no mass at all. 
+    InvalidDistribution,
+    // Invalid precode: 
+    // 1. A parity symbol must cover at least one source symbol. 
+    // 2. A source symbol must join at least one parity check. 
+    InvalidPrecode,
+    // Compaction failed: 
+    // 1. Entries to compact are not committed yet. 
+    // 2. Entries to compact are not in local log. 
+    CompactionFailed { at: usize },
+    // Reconfiguration failed: 
+    // 1. Another membership change is still in progress. 
+    // 2. A voter cannot be turned into a learner directly. 
+    ReconfigureFailed { id: ProposalId },
+    // Leadership transfer failed: 
+    // 1. The server is not the leader. 
+    // 2. The target is not a voter of the configuration. 
+    TransferFailed { target: RaftId },
+    // Read failed: 
+    // 1. The server is neither the leader nor knows one to forward it to. 
+    // 2. The leader stepped down before confirming it, leader is the one known then. 
+    ReadFailed { id: ProposalId, leader: Option<RaftId> },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn session_range() {
+        let id = ProposalId::session((1 << 31) - 1, 7);
+        assert_eq!((id.client(), id.seq()), ((1 << 31) - 1, 7));
+        // reserved ids never collide with a session
+        assert!(id < ProposalId::leave(0) && id < ProposalId::noop(Term(0)));
+        assert!(std::panic::catch_unwind(|| ProposalId::session(1 << 31, 0)).is_err());
+        assert!(std::panic::catch_unwind(|| ProposalId::session(u32::MAX, 0)).is_err());
+    }
 }
@@ -0,0 +1,126 @@
+//! Outcomes of proposals submitted to a server
+use crate::*;
+use std::collections::{HashMap, HashSet};
+
+// What became of a proposal:
+// 1. Committed: the entry is committed at the given log index, it is safe to reply to the client.
+// 2. Discarded: the entry is overwritten by a leader of a newer term, the proposal can be resubmitted.
+// 3. NotLeader: the server the proposal was forwarded to cannot take it,
+//    the hint is the leader it knows of, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Committed(usize),
+    Discarded,
+    NotLeader(Option<RaftId>),
+}
+
+// Proposals submitted to this server, waiting for an outcome
+// - a proposal dropped along with a log that does not match an installed snapshot is reported discarded,
+//   though it may be covered by the snapshot, so the client should retry under a session
+// - a forwarded proposal that never shows up in local log is likely lost on the way to the leader,
+//   it is reported as rejected without a hint once it expires
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Outcomes {
+    pending: HashSet<ProposalId>,
+    // forwarded proposals not seen in local log yet, and the tick they were forwarded at
+    forwarded: HashMap<ProposalId, u64>,
+    clock: u64,
+    done: Vec<(ProposalId, Outcome)>,
+}
+
+impl Outcomes {
+    pub(crate) fn new() -> Self {
+        Self { pending: HashSet::new(), forwarded: HashMap::new(), clock: 0, done: vec![] }
+    }
+    // wait for the outcome of a proposal
+    pub(crate) fn submit(&mut self, id: ProposalId) {
+        self.pending.insert(id);
+    }
+    // whether a proposal still waits for its outcome
+    pub(crate) fn pending(&self, id: ProposalId) -> bool {
+        self.pending.contains(&id)
+    }
+    // wait for the outcome of a proposal forwarded to the leader
+    pub(crate) fn forward(&mut self, id: ProposalId) {
+        self.pending.insert(id);
+        self.forwarded.insert(id, self.clock);
+    }
+    // tick, and give up on forwarded proposals that are not in local log after given ticks
+    // - a forwarded proposal found in the uncommitted part of local log waits to be committed or discarded
+    pub(crate) fn expire<Proposal>(&mut self, bound: u64, uncommitted: std::ops::Range<usize>, disk: &impl Persistor<Proposal>) {
+        self.clock += 1;
+        let expired = self.forwarded.iter().filter(|(_, at)| self.clock - **at >= bound).map(|(id, _)| *id).collect::<Vec<_>>();
+        if expired.is_empty() { return }
+        let local = uncommitted.filter_map(|at| disk.id(at)).collect::<HashSet<_>>();
+        for id in expired {
+            self.forwarded.remove(&id);
+            if !local.contains(&id) { self.report(id, Outcome::NotLeader(None)) }
+        }
+    }
+    // report pending proposals among entries newly committed in a range of local log
+    pub(crate) fn commit<Proposal>(&mut self, range: std::ops::Range<usize>, disk: &mut impl Persistor<Proposal>) {
+        if self.pending.is_empty() || range.is_empty() { return }
+        let start = range.start.max(disk.offset().1);
+        for (i, (_, id, _)) in disk.slice(range).into_iter().enumerate() {
+            self.report(id, Outcome::Committed(start + i));
+        }
+    }
+    // report pending proposals among entries overwritten in local log
+    pub(crate) fn discard(&mut self, ids: Vec<ProposalId>) {
+        for id in ids { self.report(id, Outcome::Discarded) }
+    }
+    // report a proposal rejected by the server it was forwarded to
+    pub(crate) fn reject(&mut self, id: ProposalId, leader: Option<RaftId>) {
+        self.report(id, Outcome::NotLeader(leader));
+    }
+    // report a pending proposal, at most once
+    fn report(&mut self, id: ProposalId, outcome: Outcome) {
+        if !self.pending.remove(&id) { return }
+        self.forwarded.remove(&id);
+        self.done.push((id, outcome));
+    }
+    // outcomes reported so far
+    pub(crate) fn take(&mut self) -> Vec<(ProposalId, Outcome)> {
+        std::mem::take(&mut self.done)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outcome_once() {
+        let mut disk = MockPersistor::<usize>::new();
+        let mut outcomes = Outcomes::new();
+        for i in 0..6 { disk.push(Entry::Command(i), ProposalId(i as u64), Term(1)); }
+        for i in [1, 3, 4] { outcomes.submit(ProposalId(i)); }
+        outcomes.commit(0..4, &mut disk);
+        assert_eq!(outcomes.take(), vec![(ProposalId(1), Outcome::Committed(1)), (ProposalId(3), Outcome::Committed(3))]);
+        // entries 4.. are overwritten by a newer term
+        assert_eq!(disk.append(4, vec![(Entry::Command(9), ProposalId(9), Term(2))]), 5);
+        outcomes.discard(disk.discarded());
+        outcomes.reject(ProposalId(3), None);
+        outcomes.commit(0..5, &mut disk);
+        assert_eq!(outcomes.take(), vec![(ProposalId(4), Outcome::Discarded)]);
+        assert_eq!(disk.discarded(), vec![]);
+    }
+
+    #[test]
+    fn outcome_expire() {
+        let mut disk = MockPersistor::<usize>::new();
+        let mut outcomes = Outcomes::new();
+        for i in 0..3 { outcomes.forward(ProposalId(i)); }
+        // one forwarded proposal reaches local log, another is rejected
+        disk.push(Entry::Command(1), ProposalId(1), Term(1));
+        outcomes.reject(ProposalId(2), Some(RaftId(0)));
+        for _ in 0..9 { outcomes.expire(10, 0..1, &disk); }
+        assert_eq!(outcomes.take(), vec![(ProposalId(2), Outcome::NotLeader(Some(RaftId(0))))]);
+        outcomes.expire(10, 0..1, &disk);
+        assert_eq!(outcomes.take(), vec![(ProposalId(0), Outcome::NotLeader(None))]);
+        // the one in local log still gets committed
+        for _ in 0..20 { outcomes.expire(10, 0..1, &disk); }
+        outcomes.commit(0..1, &mut disk);
+        assert_eq!(outcomes.take(), vec![(ProposalId(1), Outcome::Committed(0))]);
+    }
+}
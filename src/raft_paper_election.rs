@@ -1,6 +1,6 @@
 use crate::*;
 use serde::{Serialize, Deserialize};
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::{HashMap, HashSet}, fmt::Debug};
 
 // Leader election in a term: 
 // - To get elected, the candidate's term must be 'up-to-date' to a majority of servers.
@@ -14,25 +14,93 @@ impl<Proposal> RaftPaperImpl<Proposal> where
     // - reset election timeout
     // - send request
     pub fn coup_détat(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        self.campaign(false, adaptor, disk);
+    }
+    // start an election, on timeout or on TimeoutNow
+    pub(crate) fn campaign(&mut self, transfer: bool, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
         // normally the leader will not start a coup d'état
         // (unless you are the president of south korea in 2025)
         if matches!(self.role, PaperRole::Leader { .. }) { return }
+        // a learner or a server outside the configuration never starts one
+        if !self.membership.config().is_voter(self.id) { return }
         println!("RAFT :: {:?} coup_détat", self.id);
         // increase current term
         // in this term, vote for self
         self.term = self.term + 1;
-        self.role = PaperRole::Candidate { count: 1 };
+        self.role = PaperRole::Candidate { votes: HashSet::new() };
         self.vote = Some(self.id);
         disk.persist(self.term, self.vote);
-        self.timeout_elect = rand::random::<u64>() % self.bound_elect;
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
         // ask for vote from all other servers
-        for id in self.peers.iter().copied() {
+        for id in self.membership.config().members() {
             if id == self.id { continue }
-            adaptor.send(id, RaftPaperMsg::VoteReq { last: disk.last(), candidate: (self.term, self.id)});
+            adaptor.send(id, RaftPaperMsg::VoteReq { last: disk.last(), candidate: (self.term, self.id), transfer });
         }
+        // the vote for itself counts like any other, so a single server elects itself
+        self.handle_vote_ack(self.id, self.term, disk);
+    }
+    // handle TimeoutNow from a leader that hands over leadership
+    // - start an election at once, without waiting for election timeout
+    // - ignore it if it is from a previous term
+    pub fn handle_timeout_now(&mut self, (term, leader): (Term, RaftId), adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        if term < self.term { return }
+        println!("RAFT :: {:?} timeout now from {leader:?}", self.id);
+        self.campaign(true, adaptor, disk);
+    }
+    // become a pre-candidate and ask whether peers would vote for this server in next term
+    // - neither term nor vote is changed, so nothing is persisted
+    // - reset election timeout
+    pub fn pre_vote(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        if matches!(self.role, PaperRole::Leader { .. }) { return }
+        if !self.membership.config().is_voter(self.id) { return }
+        println!("RAFT :: {:?} pre_vote", self.id);
+        self.role = PaperRole::PreCandidate { votes: HashSet::new() };
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+        for id in self.membership.config().members() {
+            if id == self.id { continue }
+            adaptor.send(id, RaftPaperMsg::PreVoteReq { last: disk.last(), candidate: (self.term + 1, self.id) });
+        }
+        self.handle_pre_vote_ack(self.id, self.term + 1, adaptor, disk);
+    }
+    // handle pre-vote request
+    // - ignore candidates outside the configuration
+    // - reject if self.term >= candidate.term
+    // - reject if a leader is still heard from, which is the case until election timeout
+    // - reject if current log is considered more 'up-to-date'
+    // - nothing is changed on this server
+    pub fn handle_pre_vote_req(&mut self,
+        (cand_term, cand_id): (Term, RaftId),
+        last: (Term, usize),
+        adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>
+    ) {
+        if !self.membership.config().is_voter(cand_id) { return }
+        let reject = self.term >= cand_term
+            || matches!(self.role, PaperRole::Leader { .. } | PaperRole::Follower { .. })
+            || disk.last() > last;
+        let msg = if reject {
+            RaftPaperMsg::VoteRej { term: self.term }
+        } else {
+            RaftPaperMsg::PreVoteAck { from: self.id, term: cand_term }
+        };
+        println!("RAFT :: pre-vote {:?} -> {cand_id:?} :: {msg:?}", self.id);
+        adaptor.send(cand_id, msg);
+    }
+    // handle pre-vote acknowledge
+    // - it is valid if the server is still a pre-candidate, and it is for next term
+    // - start an election if voters form a quorum of the configuration
+    pub fn handle_pre_vote_ack(&mut self, from: RaftId, term: Term, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let PaperRole::PreCandidate { votes } = &mut self.role else { return };
+        if term != self.term + 1 { return }
+        votes.insert(from);
+        if !self.membership.config().quorum(|x| votes.contains(&x)) { return }
+        self.coup_détat(adaptor, disk);
     }
     // handle vote request
+    // - ignore candidates outside the configuration, so that removed servers cannot disrupt the cluster
+    // - in lease mode, ignore candidates within bound_elect ticks since hearing from the leader,
+    //   unless the election is started by TimeoutNow
     // - reject vote if self.term > candidate.term
+    // - adopt candidate.term if it is newer, which resets the vote
     // - reject vote if current server has already voted in (self.term, candidate.term)
     //   - for the same candidate, it only asks for vote once in each term
     //     - we can infer that the candidate must increased its term
@@ -43,11 +111,21 @@ impl<Proposal> RaftPaperImpl<Proposal> where
     pub fn handle_vote_req(&mut self, 
         (cand_term, cand_id): (Term, RaftId),
         (last_term, last_index): (Term, usize),
+        transfer: bool,
         adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>
     ) {
+        if !self.membership.config().is_voter(cand_id) { return }
+        if self.lease.is_some() && !transfer && self.timeout_lease < self.bound_elect { return }
         let reject = self.term > cand_term && {println!("RAFT :: reject vote because current term is larger"); true};
+        // a newer term resets the vote, and the server steps down
+        if self.term < cand_term {
+            self.term = cand_term;
+            self.vote = None;
+            self.role = PaperRole::Candidate { votes: HashSet::new() };
+            self.fail_reads(adaptor);
+        }
         let reject = reject || (
-            !self.vote.is_none() && 
+            self.vote.is_some() && 
             self.vote != Some(cand_id) && {println!("RAFT :: reject vote, already voted for {:?}", self.vote.unwrap()); true});
         let reject = reject || (
             disk.last() > (last_term, last_index)
@@ -56,7 +134,9 @@ impl<Proposal> RaftPaperImpl<Proposal> where
             RaftPaperMsg::VoteRej { term: self.term }
         } else {
             self.vote = Some(cand_id);
-            RaftPaperMsg::VoteAck { term: cand_term }
+            // granting a vote restarts the election timer, like hearing from a leader
+            self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+            RaftPaperMsg::VoteAck { from: self.id, term: cand_term }
         };
         disk.persist(self.term, self.vote);
         println!("RAFT :: vote {:?} -> {cand_id:?} :: {msg:?}", self.id);
@@ -66,33 +146,163 @@ impl<Proposal> RaftPaperImpl<Proposal> where
     // - vote is valid if and only if:
     //   - current the server is still a candidate
     //   - the vote is actually a vote for current term
-    // - become a leader if vote count exceeds majority
-    pub fn handle_vote_ack(&mut self, term: Term, disk: &mut impl Persistor<Proposal>) {
+    // - become a leader if voters form a quorum of the configuration
+    pub fn handle_vote_ack(&mut self, from: RaftId, term: Term, disk: &mut impl Persistor<Proposal>) {
         // if current server is not a candidate, do nothing
-        let PaperRole::Candidate { count } = &self.role else { return };
+        let PaperRole::Candidate { votes } = &mut self.role else { return };
         // if vote is for previous terms, do nothing
         if term < self.term { return };
-        let count = *count + 1;
-        self.role = if 2 * count < self.peers.len() {
-            println!("RAFT :: {:?} vote count {count:?} ", self.id);
-            // update vote count only
-            PaperRole::Candidate { count }
-        } else {
-            println!("RAFT :: {:?} become leader", self.id);
-            // update role if enough vote is collected
-            PaperRole::Leader {
-                matched: HashMap::from_iter(self.peers.iter().map(|x| (*x, 0))),
-                guessed: HashMap::from_iter(self.peers.iter().map(|x| (*x, disk.last().1)))
-            }
+        votes.insert(from);
+        let config = self.membership.config();
+        if !config.quorum(|x| votes.contains(&x)) {
+            println!("RAFT :: {:?} vote count {:?} ", self.id, votes.len());
+            return
         }
+        println!("RAFT :: {:?} become leader", self.id);
+        // update role if enough vote is collected
+        let members = config.members();
+        self.role = PaperRole::Leader {
+            matched: HashMap::from_iter(members.iter().map(|x| (*x, 0))),
+            guessed: HashMap::from_iter(members.iter().map(|x| (*x, disk.last().1))),
+            installing: HashMap::from_iter(members.iter().map(|x| (*x, (0, 0)))),
+            responded: HashSet::new(),
+            heard: HashMap::new(),
+        };
+        // entries of earlier terms are only committed along with an entry of the current term
+        disk.push(Entry::Noop, ProposalId::noop(self.term), self.term);
+        // reads and rounds of an earlier term may miss entries committed since then
+        self.reads.clear();
+        self.sent.clear();
+    }
+    // check once per election timeout that a quorum still responds to the leader
+    // - a leader cut off from a majority steps down, so clients stop proposing to it
+    // - otherwise tracking starts over
+    pub(crate) fn check_quorum(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>) {
+        let PaperRole::Leader { responded, .. } = &mut self.role else { return };
+        responded.insert(self.id);
+        if self.membership.config().quorum(|x| responded.contains(&x)) {
+            responded.clear();
+            self.timeout_elect = 0;
+            return
+        }
+        println!("RAFT :: {:?} step down, lost contact with a quorum", self.id);
+        self.role = PaperRole::Candidate { votes: HashSet::new() };
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+        self.fail_reads(adaptor);
     }
     // handle vote rejection
     // - the candidate will use the term to update itself
-    pub fn handle_vote_rej(&mut self, term: Term, disk: &mut impl Persistor<Proposal>) {
+    pub fn handle_vote_rej(&mut self, term: Term, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
         if term <= self.term { return }
         self.term = term;
-        self.role = PaperRole::Candidate { count: 0 };
+        self.role = PaperRole::Candidate { votes: HashSet::new() };
         self.vote = None;
         disk.persist(self.term, self.vote);
+        self.fail_reads(adaptor);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    #[test]
+    fn mock_fifo_minority() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        // servers 2, 3 and 4 are offline, two votes are not a majority of the cluster
+        nodes[0].coup_détat(&adaptors[0], &mut disks[0]);
+        while nodes[1].handle(&adaptors[1], &mut disks[1]) {}
+        assert_eq!(nodes[1].vote, Some(RaftId(0)));
+        while nodes[0].handle(&adaptors[0], &mut disks[0]) {}
+        assert!(!matches!(nodes[0].role, PaperRole::Leader { .. }));
+    }
+
+    #[test]
+    fn mock_fifo_newer_term() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..3).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(3)));
+        let adaptors = (0..3).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 3];
+        let mut nodes = (0..3).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        // server 1 wins term 1 with the vote of server 2, server 0 never hears of it
+        nodes[1].coup_détat(&adaptors[1], &mut disks[1]);
+        while adaptors[0].receive().is_some() {}
+        while nodes[2].handle(&adaptors[2], &mut disks[2]) {}
+        while nodes[1].handle(&adaptors[1], &mut disks[1]) {}
+        assert!(matches!(nodes[1].role, PaperRole::Leader { .. }));
+        while adaptors[0].receive().is_some() {}
+        while adaptors[2].receive().is_some() {}
+        // a vote in term 1 does not bind term 2, and the leader of term 1 steps down
+        nodes[0].term = Term(1);
+        nodes[0].coup_détat(&adaptors[0], &mut disks[0]);
+        while nodes[2].handle(&adaptors[2], &mut disks[2]) {}
+        assert_eq!((nodes[2].term, nodes[2].vote), (Term(2), Some(RaftId(0))));
+        while nodes[1].handle(&adaptors[1], &mut disks[1]) {}
+        assert!(!matches!(nodes[1].role, PaperRole::Leader { .. }));
+        assert_eq!(nodes[1].term, Term(2));
+        while nodes[0].handle(&adaptors[0], &mut disks[0]) {}
+        assert!(matches!(nodes[0].role, PaperRole::Leader { .. }));
+    }
+
+    #[test]
+    fn mock_fifo_single() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(1)));
+        let adaptor = MockAdaptor::<M, _>::new(RaftId(0), network.clone());
+        let mut disk = MockPersistor::<P>::new();
+        let mut node = RaftPaperImpl::new(RaftId(0), 10, vec![], 100, 2, &mut disk);
+        // the vote for itself is a majority of one
+        for _ in 0..100 { node.tick(&adaptor, &mut disk); }
+        assert!(matches!(node.role, PaperRole::Leader { .. }));
+    }
+
+    #[test]
+    fn mock_fifo_stable() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        // followers hear from the leader every round, so on a lossless network
+        // none of them times out once a leader is elected
+        let mut term = None;
+        for p in 0..2000 {
+            for i in 0..5 {
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+            }
+            if p == 500 { term = leader(&nodes).map(|j| nodes[j].term) }
+        }
+        assert!(term.is_some());
+        assert!(nodes.iter().all(|x| Some(x.term) == term));
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, marker::PhantomData};
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Debug, marker::PhantomData};
 use serde::{Deserialize, Serialize};
 
 use crate::*;
@@ -9,16 +9,39 @@ pub struct RaftPaperImpl<Proposal> where
     // constant parameters
     pub(crate) id: RaftId,
     pub(crate) batch: usize,
-    pub(crate) peers: Vec<RaftId>,
+    pub(crate) chunk: usize,
+    pub(crate) prevote: bool,
+    pub(crate) hint: bool,
+    pub(crate) lease: Option<u64>,
     pub(crate) phantom: PhantomData<Proposal>,
     // non-volatile states
     pub(crate) term: Term,
     pub(crate) vote: Option<RaftId>,
     // volatile states
+    pub(crate) membership: Membership,
     pub(crate) role: PaperRole,
     pub(crate) commitable: usize,
+    // entries 0..last_applied are fed to the state machine
+    pub(crate) last_applied: usize,
+    // proposals submitted to this server, and their outcomes
+    pub(crate) outcomes: Outcomes,
+    pub(crate) receiving: Option<((Term, usize), Vec<u8>)>,
+    // target of leadership transfer, and ticks since it started
+    pub(crate) transfer: Option<(RaftId, u64)>,
+    // replication rounds sent as the leader
+    pub(crate) round: u64,
+    // reads waiting for the leader to confirm leadership
+    pub(crate) reads: Vec<PendingRead>,
+    // reads with confirmed read index, waiting for the state machine
+    pub(crate) ready: Vec<(ProposalId, usize)>,
+    // reads that failed, reported along with ready reads
+    pub(crate) failed: Vec<RaftErr>,
+    // ticks since start, and the tick each recent replication round was sent at
+    pub(crate) clock: u64,
+    pub(crate) sent: VecDeque<(u64, u64)>,
     pub(crate) timeout_elect: u64,
     pub(crate) timeout_heart: u64,
+    pub(crate) timeout_lease: u64,
     pub(crate) bound_elect: u64,
     pub(crate) bound_heart: u64,
 }
@@ -27,15 +50,26 @@ pub struct RaftPaperImpl<Proposal> where
 pub enum PaperRole {
     Leader { 
         matched: HashMap<RaftId, usize>, 
-        guessed: HashMap<RaftId, usize> 
+        guessed: HashMap<RaftId, usize>,
+        // byte offset of snapshot sent to lagging followers, and the tick a chunk was last sent
+        installing: HashMap<RaftId, (usize, u64)>,
+        // peers heard from since the last quorum check
+        responded: HashSet<RaftId>,
+        // the tick of the latest round each peer acknowledged, in lease mode
+        heard: HashMap<RaftId, u64>,
     },
     Follower { leader: RaftId },
-    Candidate { count: usize },
+    // servers that would vote for this server in next term
+    PreCandidate { votes: HashSet<RaftId> },
+    // servers that voted for this candidate in current term
+    Candidate { votes: HashSet<RaftId> },
 }
 
 impl<Proposal> RaftPaperImpl<Proposal> where 
     Proposal: Serialize + for<'de> Deserialize<'de> + Clone + Debug
 {
+    // peers: initial voters other than this server
+    // - a server joining a running cluster learns the configuration from the leader
     pub fn new(
         id: RaftId, batch: usize, 
         peers: Vec<RaftId>, 
@@ -45,58 +79,199 @@ impl<Proposal> RaftPaperImpl<Proposal> where
     ) -> Self {
         let (term, vote) = disk.load();
         Self {
-            role: PaperRole::Candidate { count: 0 },
+            role: PaperRole::Candidate { votes: HashSet::new() },
+            membership: Membership::load(Config::new([peers, vec![id]].concat()), disk),
             commitable: disk.commitable(),
-            id, batch, peers, term, vote, phantom: PhantomData, 
-            bound_elect, timeout_elect: rand::random::<u64>() % bound_elect,
-            bound_heart, timeout_heart: 0
+            last_applied: 0,
+            outcomes: Outcomes::new(),
+            receiving: None,
+            transfer: None,
+            round: 0,
+            reads: vec![],
+            ready: vec![],
+            failed: vec![],
+            clock: 0,
+            sent: VecDeque::new(),
+            chunk: 1 << 16,
+            prevote: false,
+            hint: true,
+            lease: None,
+            id, batch, term, vote, phantom: PhantomData, 
+            bound_elect, timeout_elect: rand::random::<u64>() % bound_elect.div_ceil(2),
+            bound_heart, timeout_heart: 0,
+            timeout_lease: bound_elect,
         }
     }
+    // size of snapshot chunks in bytes
+    pub fn with_chunk(mut self, chunk: usize) -> Self {
+        self.chunk = chunk.max(1);
+        self
+    }
+    // run a pre-vote round before starting an election
+    // - a partitioned server does not inflate its term, and cannot disrupt a healthy leader when it is back
+    pub fn with_prevote(mut self, prevote: bool) -> Self {
+        self.prevote = prevote;
+        self
+    }
+    // backtrack a rejected follower by the conflict hint it returns
+    // - without it the leader halves the guessed prefix on every rejection, a round trip each
+    pub fn with_hint(mut self, hint: bool) -> Self {
+        self.hint = hint;
+        self
+    }
+    // let the leader serve reads without a replication round while it holds a lease
+    // - the lease lasts bound_elect - margin ticks since a round acknowledged by a quorum was sent
+    // - a follower votes for no other candidate within bound_elect ticks since it heard from the leader,
+    //   so every server in the cluster must use the same setting
+    // - clock drift: the margin must cover the ticks a follower can count ahead of the leader
+    //   within bound_elect ticks, otherwise a new leader may commit writes the lease holder never sees
+    pub fn with_lease(mut self, margin: u64) -> Self {
+        self.lease = Some(margin);
+        self
+    }
+    // join as a learner, which never starts an election until it is promoted
+    // - the leader should add it with add_learner
+    pub fn with_learner(mut self, learner: bool) -> Self {
+        if learner { self.membership.rebase(self.membership.base().learn(self.id)) }
+        self
+    }
     pub fn handle(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> bool {
         let Some(msg) = adaptor.receive() else { return false };
         match msg {
-            RaftPaperMsg::ProposalReq { proposal, id } 
-                => {let _ = self.propose(proposal, id, adaptor, disk);},
-            RaftPaperMsg::ReplicateReq { leader, prefix, patch, commit } 
-                => self.handle_replicate(leader, prefix, patch, commit, adaptor, disk),
-            RaftPaperMsg::ReplicateAck { from, sync, tail }
-                => self.handle_replicate_ack(from, sync, tail, disk),
-            RaftPaperMsg::ReplicateRej { from, term, at }
-                => self.handle_replicate_rej(from, term, at, disk),
-            RaftPaperMsg::VoteReq { candidate, last }
-                => self.handle_vote_req(candidate, last, adaptor, disk),
-            RaftPaperMsg::VoteAck { term }
-                => self.handle_vote_ack(term, disk),
+            RaftPaperMsg::ProposalReq { proposal, id, from } 
+                => self.handle_proposal(proposal, id, from, adaptor, disk),
+            RaftPaperMsg::ProposalRej { id, leader }
+                => self.outcomes.reject(id, leader),
+            RaftPaperMsg::ReplicateReq { leader, prefix, patch, commit, round } 
+                => self.handle_replicate(leader, prefix, patch, (commit, round), adaptor, disk),
+            RaftPaperMsg::ReplicateAck { from, sync, tail, round }
+                => self.handle_replicate_ack(from, sync, tail, round, adaptor, disk),
+            RaftPaperMsg::ReplicateRej { from, term, at, hint }
+                => self.handle_replicate_rej(from, term, at, hint, adaptor, disk),
+            RaftPaperMsg::InstallSnapshot { leader, last, config, offset, chunk, done }
+                => self.handle_install_snapshot(leader, last, config, offset, chunk, done, adaptor, disk),
+            RaftPaperMsg::SnapshotAck { from, last, offset }
+                => self.handle_snapshot_ack(from, last, offset, adaptor, disk),
+            RaftPaperMsg::ReadIndexReq { id, from }
+                => self.handle_read_index(id, from, adaptor, disk),
+            RaftPaperMsg::ReadIndexAck { id, index }
+                => self.handle_read_index_ack(id, index),
+            RaftPaperMsg::ReadIndexRej { id, leader }
+                => self.failed.push(RaftErr::ReadFailed { id, leader }),
+            RaftPaperMsg::TimeoutNow { leader }
+                => self.handle_timeout_now(leader, adaptor, disk),
+            RaftPaperMsg::PreVoteReq { candidate, last }
+                => self.handle_pre_vote_req(candidate, last, adaptor, disk),
+            RaftPaperMsg::PreVoteAck { from, term }
+                => self.handle_pre_vote_ack(from, term, adaptor, disk),
+            RaftPaperMsg::VoteReq { candidate, last, transfer }
+                => self.handle_vote_req(candidate, last, transfer, adaptor, disk),
+            RaftPaperMsg::VoteAck { from, term }
+                => self.handle_vote_ack(from, term, disk),
             RaftPaperMsg::VoteRej { term }
-                => self.handle_vote_rej(term, disk),
+                => self.handle_vote_rej(term, adaptor, disk),
         } true
     }
     // submit a proposal to a server
-    // - the proposal id must be distinct, even if it is a resubmission
+    // - the proposal id must be distinct, unless it is a retry within a client session, see Sessions
+    // - once accepted, its outcome is reported by take_outcomes
+    // - a leader that hands over leadership rejects proposals
     pub fn propose(&mut self, proposal: Proposal, id: ProposalId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
         match self.role {
             // a follower cannot handle request itself, but can redirect it to leader
             PaperRole::Follower { leader } => {
-                adaptor.send(leader, RaftPaperMsg::ProposalReq { proposal, id });
+                self.outcomes.forward(id);
+                adaptor.send(leader, RaftPaperMsg::ProposalReq { proposal, id, from: self.id });
                 Ok(())
             }
             // a candidate cannot effectively handle this
-            PaperRole::Candidate { .. } => Err(RaftErr::ProposalFailed { id }),
+            PaperRole::Candidate { .. } | PaperRole::PreCandidate { .. } => Err(RaftErr::ProposalFailed { id }),
+            PaperRole::Leader { .. } if self.transfer.is_some() => Err(RaftErr::ProposalFailed { id }),
             // a leader can locally 
             PaperRole::Leader { .. } => {
+                self.outcomes.submit(id);
                 // push a new log item to current log
-                disk.push(proposal, id, self.term);
+                disk.push(Entry::Command(proposal), id, self.term);
                 // try to replicate once
                 self.replicate(adaptor, disk);
                 Ok(())
             }
         }
     }
+    // request a membership change to given voters on the leader
+    // - the cluster first moves to joint consensus C_old,new, then to C_new once that is committed
+    // - the outcome reported for the id is that of C_old,new, after which the change cannot be undone
+    // - only one change can be in progress at a time
+    pub fn reconfigure(&mut self, voters: Vec<RaftId>, id: ProposalId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        let (PaperRole::Leader { .. }, None) = (&self.role, self.transfer) else { return Err(RaftErr::ProposalFailed { id }) };
+        if self.membership.config().joint.is_some() { return Err(RaftErr::ReconfigureFailed { id }) }
+        let config = self.membership.config().enter(voters);
+        self.outcomes.submit(id);
+        self.configure(config, id, disk);
+        self.replicate(adaptor, disk);
+        Ok(())
+    }
+    // add a learner on the leader, which receives log entries but is counted in no quorum
+    // - a learner is promoted once it is among voters of a later reconfiguration
+    // - no joint consensus is needed, as quorums stay the same
+    pub fn add_learner(&mut self, learner: RaftId, id: ProposalId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        let (PaperRole::Leader { .. }, None) = (&self.role, self.transfer) else { return Err(RaftErr::ProposalFailed { id }) };
+        let config = self.membership.config();
+        if config.joint.is_some() || config.is_voter(learner) { return Err(RaftErr::ReconfigureFailed { id }) }
+        let config = config.learn(learner);
+        self.outcomes.submit(id);
+        self.configure(config, id, disk);
+        self.replicate(adaptor, disk);
+        Ok(())
+    }
+    // discard log entries 0..at, which are covered by a state machine snapshot
+    // - only committed entries can be compacted
+    pub fn compact(&mut self, at: usize, snapshot: Vec<u8>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        if at > self.commitable { return Err(RaftErr::CompactionFailed { at }) }
+        if at <= disk.offset().1 { return Ok(()) }
+        let Some(term) = disk.term(at - 1) else { return Err(RaftErr::CompactionFailed { at }) };
+        disk.compact(at, term, snapshot);
+        self.membership.compact(at);
+        Ok(())
+    }
+    // compact the log up to the last applied entry, with a snapshot of the state machine
+    pub fn snapshot(&mut self, machine: &impl StateMachine<Proposal>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        self.compact(self.last_applied, machine.snapshot(), disk)
+    }
+    // feed newly committed proposals to the state machine, each exactly once and in log order
+    // - entries covered by the snapshot are restored from it instead, which happens after a restart
+    //   or once a snapshot is installed, so the state machine may be volatile
+    pub fn apply(&mut self, machine: &mut impl StateMachine<Proposal>, disk: &mut impl Persistor<Proposal>) {
+        let (_, offset) = disk.offset();
+        if self.last_applied < offset {
+            if let Some(snapshot) = disk.snapshot() { machine.restore(offset, snapshot) }
+            self.last_applied = offset;
+        }
+        if self.last_applied >= self.commitable { return }
+        for (i, (entry, id, _)) in disk.slice(self.last_applied..self.commitable).into_iter().enumerate() {
+            if let Entry::Command(proposal) = entry { machine.apply(self.last_applied + i, proposal, id); }
+        }
+        self.last_applied = self.commitable;
+    }
+    // outcomes of proposals submitted to this server, reported since the last call
+    // - each proposal gets at most one outcome
+    pub fn take_outcomes(&mut self) -> Vec<(ProposalId, Outcome)> {
+        self.outcomes.take()
+    }
     // tick timeout, do what is needed
     pub fn tick(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
         self.timeout_elect += 1;
         self.timeout_heart += 1;
+        self.outcomes.expire(self.bound_elect, self.commitable..disk.last().1, disk);
+        self.timeout_lease += 1;
+        self.clock += 1;
+        self.transfer_tick();
         if self.timeout_elect >= self.bound_elect {
+            self.check_quorum(adaptor);
+        }
+        if self.timeout_elect >= self.bound_elect && self.prevote {
+            self.pre_vote(adaptor, disk);
+        } else if self.timeout_elect >= self.bound_elect {
             self.coup_détat(adaptor, disk);
         }
         if self.timeout_heart >= self.bound_heart {
@@ -115,9 +290,9 @@ mod test {
         // commit: 10000/10000
         type P = usize;
         type M = RaftPaperMsg<P>;
-        let peers = (0..5).map(|i| RaftId(i)).collect::<Vec<_>>();
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
         let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
-        let mut adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
         let mut disks = vec![MockPersistor::<P>::new(); 5];
         let mut nodes = (0..5).map(|i| 
             RaftPaperImpl::new(RaftId(i), 10, 
@@ -127,9 +302,9 @@ mod test {
         ).collect::<Vec<_>>();
         for p in 0..2000 {
             for i in 0..5 {
-                while nodes[i].handle(&mut adaptors[i], &mut disks[i]) {}
-                nodes[i].tick(&mut adaptors[i], &mut disks[i]);
-                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &mut adaptors[i],&mut disks[i]);
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
             }
         }
     }
@@ -139,9 +314,33 @@ mod test {
         // commit: 4387/10000
         type P = usize;
         type M = RaftPaperMsg<P>;
-        let peers = (0..5).map(|i| RaftId(i)).collect::<Vec<_>>();
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
         let network = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 0.9, 0.01, 0.5, 0.1, 1)));
-        let mut adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        for p in 0..2000 {
+            for i in 0..5 {
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p * 5 + i, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn mock_fifo_compact() {
+        // commit: 9384/10000
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
         let mut disks = vec![MockPersistor::<P>::new(); 5];
         let mut nodes = (0..5).map(|i| 
             RaftPaperImpl::new(RaftId(i), 10, 
@@ -151,10 +350,725 @@ mod test {
         ).collect::<Vec<_>>();
         for p in 0..2000 {
             for i in 0..5 {
-                while nodes[i].handle(&mut adaptors[i], &mut disks[i]) {}
-                nodes[i].tick(&mut adaptors[i], &mut disks[i]);
-                let _ = nodes[i].propose(p * 5 + i, ProposalId((p * 5 + i) as u64), &mut adaptors[i],&mut disks[i]);
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+                if p % 100 == 99 {
+                    let at = nodes[i].commitable;
+                    nodes[i].compact(at, vec![], &mut disks[i]).unwrap();
+                    assert_eq!(disks[i].offset().1, at);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mock_fifo_install() {
+        // commit: 8546/10000
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            ).with_chunk(3)
+        ).collect::<Vec<_>>();
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the last server is offline in first half
+                if i == 4 && p < 1000 { continue }
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+                if p % 100 == 99 {
+                    let at = nodes[i].commitable;
+                    nodes[i].compact(at, format!("{at:08}").into_bytes(), &mut disks[i]).unwrap();
+                }
+            }
+        }
+        // the last server catches up through a snapshot
+        let (_, at) = disks[4].offset();
+        assert!(at > 0);
+        assert_eq!(disks[4].snapshot(), Some(format!("{at:08}").into_bytes()));
+        assert!(disks[4].commitable() >= at);
+    }
+
+    #[test]
+    fn mock_burst_install() {
+        // install: 65~771 rounds
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 0.9, 0.01, 0.5, 0.1, 1)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            ).with_chunk(64)
+        ).collect::<Vec<_>>();
+        let mut installed = None;
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the last server is offline in first half, messages to it are lost
+                if i == 4 && p < 1000 { while adaptors[i].receive().is_some() {} continue }
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+                // snapshots of 4096 bytes
+                if p % 100 == 99 && i != 4 {
+                    let at = nodes[i].commitable;
+                    nodes[i].compact(at, format!("{at:08}").repeat(512).into_bytes(), &mut disks[i]).unwrap();
+                }
             }
+            if installed.is_none() && disks[4].offset().1 > 0 { installed = Some(p - 1000) }
         }
+        println!("install: {installed:?} rounds");
+        // the last server catches up through a snapshot
+        let (_, at) = disks[4].offset();
+        assert!(installed.is_some());
+        assert_eq!(disks[4].snapshot(), Some(format!("{at:08}").repeat(512).into_bytes()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mock_fifo_reconfigure() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let (mut removed, mut commit, mut changed) = (None, 0, 0);
+        for p in 0..2000 {
+            for i in 0..5 {
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+                // a change is reported committed at its C_old,new entry
+                for (id, outcome) in nodes[i].take_outcomes() {
+                    let (20000.., Outcome::Committed(at)) = (id.0, outcome) else { continue };
+                    let (entry, x, _) = disks[i].slice(at..at + 1).remove(0);
+                    assert!(matches!(entry, Entry::Config(Config { joint: Some(_), .. })));
+                    assert_eq!(x, id);
+                    changed += 1;
+                }
+            }
+            let Some(j) = leader(&nodes) else { continue };
+            // remove the first leader and its neighbour, then add them back
+            // - a change is requested again if it is overwritten before commit
+            let i = *removed.get_or_insert(j);
+            let voters = if p < 800 { (0..5).filter(|x| *x != i && *x != (i + 1) % 5).map(|x| RaftId(x as u64)).collect() } else { peers.clone() };
+            if p >= 500 && nodes[j].membership.config() != &Config::new(voters.clone()) {
+                let _ = nodes[j].reconfigure(voters, ProposalId(20000 + p as u64), &adaptors[j], &mut disks[j]);
+            }
+            // the remaining servers keep committing without them
+            if p == 799 {
+                assert!(j != i && j != (i + 1) % 5);
+                assert_eq!(nodes[j].membership.config().voters.len(), 3);
+                commit = nodes[j].commitable;
+            }
+        }
+        // the removed leader catches up after it is added back
+        let i = removed.unwrap();
+        assert_eq!(nodes[i].membership.config(), &Config::new(peers.clone()));
+        assert!(nodes[i].commitable > commit);
+        // both changes are reported, C_new never shares an id with C_old,new, nor no-ops of different terms
+        assert!(changed >= 2);
+        let (_, last) = disks[i].last();
+        let reserved = disks[i].slice(0..last).into_iter()
+            .filter(|(entry, _, _)| matches!(entry, Entry::Config(_) | Entry::Noop)).map(|(_, id, _)| id).collect::<Vec<_>>();
+        assert!(reserved.len() >= 6);
+        assert_eq!(reserved.iter().collect::<HashSet<_>>().len(), reserved.len());
+    }
+    #[test]
+    fn mock_fifo_learner() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(6)));
+        let adaptors = (0..6).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 6];
+        // the last server joins as a learner
+        let mut nodes = (0..6).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        peers.iter().copied().filter(|x| *x != RaftId(i)).collect(), 
+        100, 2, &mut disks[i as usize]
+            ).with_learner(i == 5)
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..6).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let mut commit = 0;
+        for p in 0..2000 {
+            for i in 0..6 {
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 6 + i) as u64), &adaptors[i],&mut disks[i]);
+            }
+            let Some(j) = leader(&nodes) else { continue };
+            // add the learner, then promote it to a voter
+            // - a change is requested again if it is overwritten before commit
+            let config = if p < 1200 { Config::new(peers.clone()).learn(RaftId(5)) } else { Config::new((0..6).map(RaftId).collect()) };
+            if p >= 200 && nodes[j].membership.config() != &config {
+                let id = ProposalId(20000 + p as u64);
+                let _ = if p < 1200 { nodes[j].add_learner(RaftId(5), id, &adaptors[j], &mut disks[j]) }
+                else { nodes[j].reconfigure(config.voters.clone(), id, &adaptors[j], &mut disks[j]) };
+            }
+            // the learner follows the log, but never takes part in elections
+            if p < 1200 {
+                assert!(j != 5);
+                assert!(!nodes[5].membership.config().is_voter(RaftId(5)));
+            }
+            if p == 1199 {
+                assert!(matches!(nodes[5].role, PaperRole::Follower { .. }));
+                assert!(nodes[5].commitable > 0);
+                commit = nodes[5].commitable;
+            }
+        }
+        // the promoted learner keeps committing as a voter
+        assert_eq!(nodes[5].membership.config(), &Config::new((0..6).map(RaftId).collect()));
+        assert!(nodes[5].commitable > commit);
+    }
+
+    #[test]
+    fn mock_fifo_transfer() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let mut from = None;
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the target is offline for a while, messages to it are lost
+                if from.is_some_and(|j| i == (j + 1) % 5) && (500..700).contains(&p) { while adaptors[i].receive().is_some() {} continue }
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+            }
+            if p < 500 { from = leader(&nodes); continue }
+            let j = from.unwrap();
+            let target = RaftId(((j + 1) % 5) as u64);
+            // a transfer to an offline server is aborted after an election timeout
+            if p == 500 {
+                nodes[j].transfer_leadership(target, &adaptors[j], &mut disks[j]).unwrap();
+                assert!(nodes[j].propose(0, ProposalId(10000), &adaptors[j], &mut disks[j]).is_err());
+            }
+            if p == 650 {
+                assert_eq!(leader(&nodes), Some(j));
+                assert_eq!(nodes[j].transfer, None);
+            }
+            // a transfer to an online server hands over leadership
+            if p == 1500 {
+                nodes[j].transfer_leadership(target, &adaptors[j], &mut disks[j]).unwrap();
+            }
+            if p == 1600 {
+                assert_eq!(leader(&nodes), Some(target.0 as usize));
+            }
+        }
+    }
+
+    #[test]
+    fn mock_fifo_prevote() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        // messages sent over a partition are never delivered
+        let partition = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            ).with_prevote(true)
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let (mut from, mut term) = (None, None);
+        for p in 0..2000 {
+            for i in 0..5 {
+                // a follower is partitioned away for a while, but keeps running
+                let adaptor = if from.is_some_and(|j| i == (j + 1) % 5) && (500..1000).contains(&p) {
+                    while adaptors[i].receive().is_some() {}
+                    &isolated[i]
+                } else { &adaptors[i] };
+                while nodes[i].handle(adaptor, &mut disks[i]) {}
+                nodes[i].tick(adaptor, &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), adaptor, &mut disks[i]);
+            }
+            if p < 500 { from = leader(&nodes); continue }
+            let j = from.unwrap();
+            // the partitioned follower never bumps its term
+            let i = (j + 1) % 5;
+            if p < 1000 { assert_eq!(nodes[i].term, *term.get_or_insert(nodes[i].term)) }
+            // the leader is not disrupted once it is back
+            assert_eq!(leader(&nodes), Some(j));
+        }
+    }
+
+    #[test]
+    fn mock_fifo_check_quorum() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        // messages sent over a partition are never delivered
+        let partition = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).filter(|i| matches!(nodes[*i].role, PaperRole::Leader { .. })).collect::<Vec<_>>();
+        let mut from = None;
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the leader is partitioned away for a while, but keeps running
+                let adaptor = if from == Some(i) && (500..1000).contains(&p) {
+                    while adaptors[i].receive().is_some() {}
+                    &isolated[i]
+                } else { &adaptors[i] };
+                while nodes[i].handle(adaptor, &mut disks[i]) {}
+                nodes[i].tick(adaptor, &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), adaptor, &mut disks[i]);
+            }
+            if p < 500 { from = leader(&nodes).first().copied(); continue }
+            // within two election timeouts, the partitioned leader steps down
+            // and a new leader is elected by the majority
+            let j = from.unwrap();
+            if (700..1000).contains(&p) {
+                assert!(!matches!(nodes[j].role, PaperRole::Leader { .. }));
+                assert_eq!(leader(&nodes).len(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn mock_fifo_read_index() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        // messages sent over a partition are never delivered
+        let partition = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        // commit index of the cluster when a read is issued
+        let (mut from, mut issued, mut served, mut failed) = (None, HashMap::new(), 0, 0);
+        // reads accepted by the partitioned leader, each is either served or failed
+        let mut pending = HashSet::new();
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the leader is partitioned away for a while, but keeps running
+                let adaptor = if from == Some(i) && (500..1000).contains(&p) {
+                    while adaptors[i].receive().is_some() {}
+                    &isolated[i]
+                } else { &adaptors[i] };
+                while nodes[i].handle(adaptor, &mut disks[i]) {}
+                nodes[i].tick(adaptor, &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), adaptor, &mut disks[i]);
+                let id = ProposalId((p * 5 + i) as u64);
+                issued.insert(id, nodes.iter().map(|x| x.commitable).max().unwrap());
+                let read = nodes[i].read_index(id, adaptor, &mut disks[i]);
+                if read.is_ok() && from == Some(i) && (500..1000).contains(&p) { pending.insert(id); }
+                // the log is the state machine, every write committed before the read is seen
+                let applied = nodes[i].commitable;
+                for read in nodes[i].take_reads(applied) {
+                    match read {
+                        Ok(id) => { assert!(applied >= issued[&id]); pending.remove(&id); served += 1 }
+                        Err(RaftErr::ReadFailed { id, .. }) => { pending.remove(&id); failed += 1 }
+                        Err(err) => panic!("unexpected {err:?}"),
+                    }
+                }
+            }
+            if p < 500 { from = leader(&nodes) }
+        }
+        println!("served: {served}/10000, failed: {failed}/10000");
+        assert!(served > 0);
+        // the partitioned leader fails its pending reads once it steps down
+        assert!(failed > 0 && pending.is_empty());
+        // a server that is not the leader rejects a read with the leader it knows of
+        let j = leader(&nodes).unwrap();
+        let (k, m) = ((j + 1) % 5, (j + 2) % 5);
+        let id = ProposalId(10000);
+        adaptors[m].send(RaftId(k as u64), RaftPaperMsg::ReadIndexReq { id, from: RaftId(m as u64) });
+        while nodes[k].handle(&adaptors[k], &mut disks[k]) {}
+        while nodes[m].handle(&adaptors[m], &mut disks[m]) {}
+        let applied = nodes[m].commitable;
+        let reads = nodes[m].take_reads(applied);
+        assert!(reads.iter().any(|read| matches!(read, Err(RaftErr::ReadFailed { id: x, leader: Some(y) }) if *x == id && *y == RaftId(j as u64))));
+    }
+
+    #[test]
+    fn mock_burst_lease() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 0.9, 0.01, 0.5, 0.1, 1)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        // messages sent over a partition are always erased
+        let partition = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 1.0, 1.0, 0.0, 0.0, 1)));
+        let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            ).with_lease(10)
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        // commit index of the cluster when a read is issued
+        let (mut from, mut issued, mut leased) = (None, HashMap::new(), 0);
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the leader is partitioned away for a while, but keeps running
+                let adaptor = if from == Some(i) && (500..1000).contains(&p) {
+                    while adaptors[i].receive().is_some() {}
+                    &isolated[i]
+                } else { &adaptors[i] };
+                while nodes[i].handle(adaptor, &mut disks[i]) {}
+                nodes[i].tick(adaptor, &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), adaptor, &mut disks[i]);
+                let id = ProposalId((p * 5 + i) as u64);
+                issued.insert(id, nodes.iter().map(|x| x.commitable).max().unwrap());
+                let _ = nodes[i].read_index(id, adaptor, &mut disks[i]);
+                // the log is the state machine, every write committed before the read is seen
+                let applied = nodes[i].commitable;
+                for id in nodes[i].take_reads(applied).into_iter().flatten() {
+                    assert!(applied >= issued[&id]);
+                }
+            }
+            // no leader of a later term is elected while a lease is held
+            for i in (0..5).filter(|i| nodes[*i].leased()) {
+                assert!(nodes.iter().all(|x| !matches!(x.role, PaperRole::Leader { .. }) || x.term <= nodes[i].term));
+                leased += 1;
+            }
+            if p < 500 { from = leader(&nodes) }
+            // the lease of the partitioned leader expires within an election timeout
+            if let Some(j) = from && (600..1000).contains(&p) {
+                assert!(!nodes[j].leased());
+            }
+        }
+        println!("leased: {leased}/2000 rounds");
+        assert!(leased > 0);
+    }
+
+    #[test]
+    fn mock_fifo_apply() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut machines = vec![MockStateMachine::new(); 5];
+        let new = |i: usize, disk: &mut MockPersistor<P>| 
+            RaftPaperImpl::new(RaftId(i as u64), 10, 
+        {let mut peer = peers.clone(); peer.remove(i); peer}, 
+        100, 2, disk
+            ).with_chunk(1 << 12);
+        let mut nodes = (0..5).map(|i| new(i, &mut disks[i])).collect::<Vec<_>>();
+        for p in 0..2000 {
+            for i in 0..5 {
+                // the last server is offline in first half, messages to it are lost
+                if i == 4 && p < 1000 { while adaptors[i].receive().is_some() {} continue }
+                // a server restarts with a volatile state machine
+                if i == 3 && p == 1500 {
+                    nodes[i] = new(i, &mut disks[i]);
+                    machines[i] = MockStateMachine::new();
+                }
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), &adaptors[i],&mut disks[i]);
+                nodes[i].apply(&mut machines[i], &mut disks[i]);
+                if p % 500 == 499 {
+                    nodes[i].snapshot(&machines[i], &mut disks[i]).unwrap();
+                }
+            }
+        }
+        // every server applies the same proposals, each exactly once and in log order
+        let longest = machines.iter().max_by_key(|x| x.applied.len()).unwrap().applied.clone();
+        assert!(longest.windows(2).all(|x| x[0].0 < x[1].0));
+        assert_eq!(longest.iter().map(|x| x.1).collect::<HashSet<_>>().len(), longest.len());
+        for (node, machine) in nodes.iter().zip(machines.iter()) {
+            assert!(machine.applied.len() > 1000);
+            assert_eq!(machine.applied, longest[..machine.applied.len()]);
+            assert!(machine.applied.last().unwrap().0 < node.last_applied);
+        }
+    }
+
+    #[test]
+    fn mock_fifo_outcome() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        // messages sent over a partition are never delivered
+        let partition = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let (mut outcomes, mut accepted) = (HashMap::new(), HashSet::new());
+        let mut from = None;
+        for p in 0..2200 {
+            for i in 0..5 {
+                // the leader is partitioned away for a while, and keeps taking proposals
+                let adaptor = if from.is_some_and(|j| i == j) && (500..1000).contains(&p) {
+                    while adaptors[i].receive().is_some() {}
+                    &isolated[i]
+                } else { &adaptors[i] };
+                while nodes[i].handle(adaptor, &mut disks[i]) {}
+                nodes[i].tick(adaptor, &mut disks[i]);
+                // the last rounds let every accepted proposal get its outcome
+                let id = ProposalId((p * 5 + i) as u64);
+                if p < 2000 && nodes[i].propose(p, id, adaptor, &mut disks[i]).is_ok() { accepted.insert(id); }
+                // snapshots make the partitioned leader drop its log once it is back
+                if p % 100 == 99 {
+                    let at = nodes[i].commitable;
+                    nodes[i].compact(at, at.to_le_bytes().to_vec(), &mut disks[i]).unwrap();
+                }
+                // each proposal gets at most one outcome, on the server it was submitted to
+                for (id, outcome) in nodes[i].take_outcomes() {
+                    assert_eq!(id.0 as usize % 5, i);
+                    assert_eq!(outcomes.insert(id, (i, outcome)), None);
+                }
+            }
+            if p < 500 { from = leader(&nodes) }
+            // forwarded proposals are rejected during leadership transfer
+            if p == 1500 {
+                let j = leader(&nodes).unwrap();
+                nodes[j].transfer_leadership(RaftId(((j + 1) % 5) as u64), &adaptors[j], &mut disks[j]).unwrap();
+            }
+        }
+        let (mut committed, mut discarded, mut rejected) = (0, 0, 0);
+        let log = disks[leader(&nodes).unwrap()].slice(0..usize::MAX);
+        assert_eq!(outcomes.len(), accepted.len());
+        for (id, (i, outcome)) in outcomes {
+            match outcome {
+                Outcome::Committed(index) => {
+                    committed += 1;
+                    if let Some(x) = disks[i].slice(index..index + 1).first() { assert_eq!(x.1, id) }
+                }
+                Outcome::Discarded => { discarded += 1; assert!(log.iter().all(|x| x.1 != id)) }
+                Outcome::NotLeader(hint) => rejected += hint.is_some() as usize,
+            }
+        }
+        assert!(committed > 1000);
+        assert!(discarded > 0);
+        assert!(rejected > 0);
+    }
+
+    #[test]
+    fn mock_fifo_session() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        let mut machines = vec![Sessions::new(MockStateMachine::new()); 5];
+        let mut nodes = (0..5).map(|i| 
+            RaftPaperImpl::new(RaftId(i), 10, 
+        {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+        100, 2, &mut disks[i as usize]
+            )
+        ).collect::<Vec<_>>();
+        // each server is the client of its own session, and retries its command until it is committed
+        let mut seqs = [1; 5];
+        for p in 0..2000 {
+            for i in 0..5 {
+                while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+                nodes[i].tick(&adaptors[i], &mut disks[i]);
+                let id = ProposalId::session(i as u32 + 1, seqs[i]);
+                for (x, outcome) in nodes[i].take_outcomes() {
+                    if x == id && matches!(outcome, Outcome::Committed(_)) { seqs[i] += 1 }
+                }
+                let _ = nodes[i].propose(p, id, &adaptors[i], &mut disks[i]);
+                nodes[i].apply(&mut machines[i], &mut disks[i]);
+            }
+        }
+        // retries show up in the log, but each command is applied once, in order of its session
+        let log = disks[0].slice(0..nodes[0].last_applied);
+        assert!(log.iter().map(|x| x.1).collect::<HashSet<_>>().len() < log.len());
+        for machine in machines {
+            let applied = machine.machine.applied.iter().map(|x| x.1).collect::<Vec<_>>();
+            assert!(applied.len() > 1000);
+            for client in 1..=5 {
+                let seqs = applied.iter().filter(|x| x.client() == client).map(|x| x.seq()).collect::<Vec<_>>();
+                assert_eq!(seqs, (1..=seqs.len() as u32).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn scripted_figure_8() {
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<M>::new(5)));
+        let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+        let mut disks = vec![MockPersistor::<P>::new(); 5];
+        // no election timeout, elections are started by the script, one entry per replication
+        let new = |i: usize, disk: &mut MockPersistor<P>| 
+            RaftPaperImpl::new(RaftId(i as u64), 1, 
+        {let mut peer = peers.clone(); peer.remove(i); peer}, 
+        1 << 20, 2, disk
+            );
+        let mut nodes = (0..5).map(|i| new(i, &mut disks[i])).collect::<Vec<_>>();
+        // the scripted network: a crashed server loses every message sent to it,
+        // and messages in flight are lost once their sender crashes
+        let step = |nodes: &mut [RaftPaperImpl<P>], disks: &mut [MockPersistor<P>], i: usize, alive: &[usize]| {
+            if !alive.contains(&i) { while adaptors[i].receive().is_some() {} return }
+            while nodes[i].handle(&adaptors[i], &mut disks[i]) {}
+            nodes[i].tick(&adaptors[i], &mut disks[i]);
+        };
+        let leader = |nodes: &[RaftPaperImpl<P>], i: usize| matches!(nodes[i].role, PaperRole::Leader { .. });
+        let elect = |nodes: &mut [RaftPaperImpl<P>], disks: &mut [MockPersistor<P>], j: usize, alive: &[usize]| {
+            while !leader(nodes, j) {
+                nodes[j].coup_détat(&adaptors[j], &mut disks[j]);
+                for i in (0..5).filter(|i| *i != j).chain([j]) { step(nodes, disks, i, alive) }
+            }
+        };
+        // (a) server 0 leads, and replicates entry A to server 1 only
+        elect(&mut nodes, &mut disks, 0, &[0, 1, 2, 3, 4]);
+        nodes[0].propose(0, ProposalId(0), &adaptors[0], &mut disks[0]).unwrap();
+        for _ in 0..10 { for i in 0..5 { step(&mut nodes, &mut disks, i, &[0, 1]) } }
+        let a = disks[0].last().1 - 1;
+        assert_eq!(disks[1].slice(a..a + 1)[0].1, ProposalId(0));
+        // (b) server 4 leads with votes of 2 and 3, and crashes with entry B in its own log only
+        elect(&mut nodes, &mut disks, 4, &[2, 3, 4]);
+        nodes[4].propose(1, ProposalId(1), &adaptors[4], &mut disks[4]).unwrap();
+        for i in 0..5 { step(&mut nodes, &mut disks, i, &[]) }
+        // (c) server 0 restarts and leads again, and replicates A to server 2, then crashes
+        nodes[0] = new(0, &mut disks[0]);
+        elect(&mut nodes, &mut disks, 0, &[0, 1, 2]);
+        while disks[2].slice(a..a + 1).first().map(|x| x.1) != Some(ProposalId(0)) {
+            for i in 0..5 { step(&mut nodes, &mut disks, i, &[0, 1, 2]) }
+        }
+        // A is on a majority, but no entry of the current term is, so A is not committed
+        while nodes[0].handle(&adaptors[0], &mut disks[0]) {}
+        assert!(nodes[0].commitable <= a);
+        for i in 0..5 { step(&mut nodes, &mut disks, i, &[]) }
+        // (d) server 4 restarts and leads with a log of a later term, and overwrites A
+        nodes[4] = new(4, &mut disks[4]);
+        elect(&mut nodes, &mut disks, 4, &[1, 2, 3, 4]);
+        for _ in 0..20 { for i in 0..5 { step(&mut nodes, &mut disks, i, &[1, 2, 3, 4]) } }
+        assert_eq!(disks[1].slice(a..a + 1)[0].1, ProposalId(1));
+        // entries committed anywhere are never overwritten
+        for i in 0..5 {
+            let commit = nodes[i].commitable;
+            assert_eq!(disks[i].slice(0..commit), disks[4].slice(0..commit));
+        }
+    }
+
+    #[test]
+    fn mock_burst_backtrack() {
+        // backtrack: 49~67 rounds, halving the prefix takes 358~454 rounds
+        type P = usize;
+        type M = RaftPaperMsg<P>;
+        let peers = (0..5).map(RaftId).collect::<Vec<_>>();
+        let leader = |nodes: &[RaftPaperImpl<P>]| (0..5).find(|i| matches!(nodes[*i].role, PaperRole::Leader { .. }));
+        let mut rounds = vec![];
+        for hint in [true, false] {
+            let network = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 0.9, 0.01, 0.5, 0.1, 1)));
+            let adaptors = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), network.clone())).collect::<Vec<_>>();
+            // messages sent over a partition are never delivered
+            let partition = Arc::new(Mutex::new(MockBurstNetwork::<M>::new(5, 1.0, 1.0, 0.0, 0.0, 1)));
+            let isolated = (0..5).map(|i| MockAdaptor::<M, _>::new(RaftId(i), partition.clone())).collect::<Vec<_>>();
+            let mut disks = vec![MockPersistor::<P>::new(); 5];
+            let mut nodes = (0..5).map(|i| 
+                RaftPaperImpl::new(RaftId(i), 10, 
+            {let mut peer = peers.clone(); peer.remove(i as usize); peer}, 
+            100, 2, &mut disks[i as usize]
+                ).with_hint(hint)
+            ).collect::<Vec<_>>();
+            // the partitioned leader, and the rounds its partition starts and ends
+            let (mut from, mut start, mut healed, mut at, mut caught) = (None, 1500, None, 0, None);
+            for p in 0..3000 {
+                for i in 0..5 {
+                    // the leader is partitioned away for a while, and piles up entries that are never committed
+                    let adaptor = if from.is_some_and(|j| i == j) && p >= start && healed.is_none() {
+                        while adaptors[i].receive().is_some() {}
+                        &isolated[i]
+                    } else { &adaptors[i] };
+                    while nodes[i].handle(adaptor, &mut disks[i]) {}
+                    nodes[i].tick(adaptor, &mut disks[i]);
+                    let _ = nodes[i].propose(p, ProposalId((p * 5 + i) as u64), adaptor, &mut disks[i]);
+                }
+                // the partition starts once there is a leader
+                if p < start { continue }
+                let Some(j) = from else {
+                    from = leader(&nodes);
+                    start = p + 1;
+                    continue
+                };
+                // rounds until the old leader drops its own entries, and commits what the cluster did meanwhile
+                if let Some(healed) = healed {
+                    if nodes[j].commitable >= at {
+                        caught = Some(p - healed);
+                        break
+                    }
+                    continue
+                }
+                let Some(k) = leader(&nodes).filter(|k| *k != j) else { continue };
+                let PaperRole::Leader { guessed, .. } = &nodes[k].role else { continue };
+                // leadership moves on meanwhile, until the leader knows nothing of the old leader's log,
+                // and guesses it matches past where the two diverge
+                let (_, last) = disks[j].last();
+                let diverged = (nodes[j].commitable..last).find(|x| disks[j].term(*x) != disks[k].term(*x)).unwrap_or(last);
+                let moved = guessed[&RaftId(j as u64)] > diverged;
+                if !moved && p >= start + 100 && nodes[k].transfer.is_none() {
+                    let target = (1..5).map(|x| (k + x) % 5).find(|x| *x != j).unwrap();
+                    let _ = nodes[k].transfer_leadership(RaftId(target as u64), &adaptors[k], &mut disks[k]);
+                }
+                // the partition heals after 200 rounds, once the others have committed past that point
+                at = nodes.iter().map(|x| x.commitable).max().unwrap();
+                if p >= start + 200 && moved && at > diverged { healed = Some(p) }
+            }
+            rounds.push(caught);
+        }
+        println!("backtrack: {:?} rounds, halving the prefix: {:?} rounds", rounds[0], rounds[1]);
+        // a round trip per conflicting term beats one per halving
+        assert!(rounds[0].is_some());
+        assert!(rounds[0] < rounds[1].or(Some(usize::MAX)));
+    }
+}
@@ -1,28 +1,69 @@
 use crate::raft_nums::*;
+use crate::raft_entry::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RaftPaperMsg<Proposal> {
-    // Proposal request
-    ProposalReq { proposal: Proposal, id: ProposalId },
+    // Proposal request, forwarded from a server to the leader it knows of
+    ProposalReq { proposal: Proposal, id: ProposalId, from: RaftId },
+    // Proposal rejected by a server that is not the leader, with the leader it knows of
+    ProposalRej { id: ProposalId, leader: Option<RaftId> },
     // Replicate a segment of log items
     // Leader should send out this to all
+    // - round: counts replications of the leader, echoed in acknowledgements
     ReplicateReq {
+        round: u64,
         commit: usize,
         leader: (Term, RaftId),
         prefix: (Option<Term>, usize),
-        patch: Vec<(Proposal, ProposalId, Term)>,
+        patch: Vec<(Entry<Proposal>, ProposalId, Term)>,
     },
     // Acknowledge replication
-    ReplicateAck { from: RaftId, sync: usize, tail: usize },
+    // - round: the round of the request, none if it is not a reply to ReplicateReq
+    ReplicateAck { from: RaftId, sync: usize, tail: usize, round: Option<u64> },
     // Reject replication
-    ReplicateRej { from: RaftId, term: Term, at: usize },
+    // - hint: the term of the follower at the prefix and the first index of that term,
+    //   or none and the length of the follower log if it is shorter than the prefix
+    ReplicateRej { from: RaftId, term: Term, at: usize, hint: (Option<Term>, usize) },
+    // Install a chunk of snapshot on a follower that lags behind the snapshot point
+    // - last: the snapshot covers entries 0..last.1, and entry last.1 - 1 has term last.0
+    // - config: the configuration in effect at the snapshot point
+    // - offset: byte offset of the chunk
+    // - done: this is the last chunk
+    InstallSnapshot {
+        leader: (Term, RaftId),
+        last: (Term, usize),
+        config: Config,
+        offset: usize,
+        chunk: Vec<u8>,
+        done: bool,
+    },
+    // Acknowledge snapshot chunks, the follower has received bytes 0..offset
+    // the leader should continue from offset
+    SnapshotAck { from: RaftId, last: usize, offset: usize },
+    // Read request forwarded by a follower, identified by a distinct id
+    ReadIndexReq { id: ProposalId, from: RaftId },
+    // Read index confirmed by the leader
+    ReadIndexAck { id: ProposalId, index: usize },
+    // Read rejected by a server that is not the leader, with the leader it knows of
+    ReadIndexRej { id: ProposalId, leader: Option<RaftId> },
+    // Start an election at once, sent by a leader that hands over leadership
+    TimeoutNow { leader: (Term, RaftId) },
+    // Pre-vote request, candidate.0 is the term it would start
+    PreVoteReq {
+        candidate: (Term, RaftId),
+        last: (Term, usize),
+    },
+    // Pre-vote acknowledged, a rejection is sent as VoteRej
+    PreVoteAck { from: RaftId, term: Term },
     // Vote request
+    // - transfer: the election is started by TimeoutNow, which breaks a lease on purpose
     VoteReq {
         candidate: (Term, RaftId),
         last: (Term, usize),
+        transfer: bool,
     },
     // Vote acknowledged
-    VoteAck { term: Term },
+    VoteAck { from: RaftId, term: Term },
     // Vote rejected
     VoteRej { term: Term },
 }
\ No newline at end of file
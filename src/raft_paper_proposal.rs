@@ -1,5 +1,6 @@
 use crate::*;
 use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
 
 // Lifecycle of a proposal: 
 // - A proposal is submitted to the leader. 
@@ -10,98 +11,209 @@ use serde::{Serialize, Deserialize};
 //    (2.a) The new leader will eventually discard or apply it. 
 // - When the item is committed, reply to the client. 
 // - When the item is discarded, reply to the client. 
+// Both are reported as outcomes to the server the proposal was submitted to, see take_outcomes. 
 impl<Proposal> RaftPaperImpl<Proposal> where
     Proposal: Serialize + for<'de> Deserialize<'de> + Clone
 {
+    // handle a proposal forwarded by another server
+    // - only the leader takes it, the server it was submitted to learns of the leader known here
+    pub(crate) fn handle_proposal(&mut self, proposal: Proposal, id: ProposalId, from: RaftId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let leader = match self.role {
+            PaperRole::Leader { .. } if self.transfer.is_none() => {
+                disk.push(Entry::Command(proposal), id, self.term);
+                self.replicate(adaptor, disk);
+                return
+            }
+            // the target of leadership transfer is about to take over
+            PaperRole::Leader { .. } => self.transfer.map(|(x, _)| x),
+            PaperRole::Follower { leader } => Some(leader),
+            PaperRole::Candidate { .. } | PaperRole::PreCandidate { .. } => None,
+        };
+        adaptor.send(from, RaftPaperMsg::ProposalRej { id, leader });
+    }
+    // raise the commit index, and report proposals committed on the way
+    pub(crate) fn advance(&mut self, commit: usize, disk: &mut impl Persistor<Proposal>) {
+        if commit <= self.commitable { return }
+        self.outcomes.commit(self.commitable..commit, disk);
+        self.commitable = commit;
+        disk.commit(self.commitable);
+    }
     // try replicate based on current knowledge
     pub(crate) fn replicate(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
-        let PaperRole::Leader { guessed, .. } = &self.role else { return };
+        let PaperRole::Leader { guessed, installing, .. } = &self.role else { return };
         self.timeout_heart = 0;
+        self.round += 1;
+        if self.lease.is_some() {
+            self.sent.retain(|(_, at)| self.clock - at < self.bound_elect);
+            self.sent.push_back((self.round, self.clock));
+        }
         println!("RAFT :: {:?} replicate", self.id);
-        for id in self.peers.iter().copied() {
+        let mut resent = vec![];
+        for id in self.membership.config().members() {
             if id == self.id { continue }
+            // entries before the snapshot point cannot be sent, send snapshot instead
+            // - acknowledgements drive the transfer, a chunk is resent in case it or its acknowledgement is lost
+            if guessed[&id] < disk.offset().1 {
+                if self.clock - installing[&id].1 >= self.bound_heart {
+                    self.install(id, adaptor, disk);
+                    resent.push(id);
+                }
+                continue
+            }
             let last_index = guessed[&id].min(disk.last().1);
             let last_term = last_index.checked_add_signed(-1).map(|x| disk.term(x).unwrap_or(Term(0)));
             adaptor.send(id, RaftPaperMsg::ReplicateReq {
                 patch: disk.slice(last_index..last_index+self.batch), 
                 leader: (self.term, self.id), 
                 commit: self.commitable,
+                round: self.round,
                 prefix: (last_term, last_index)
             });
         }
+        let PaperRole::Leader { installing, .. } = &mut self.role else { return };
+        for id in resent { installing.get_mut(&id).expect("every peer should be logged").1 = self.clock }
     }
     // validate and append delta
     pub(crate) fn handle_replicate(&mut self,
         (leader_term, leader_id): (Term, RaftId),
         (prefix_term, prefix_index): (Option<Term>, usize),
-        patch: Vec<(Proposal, ProposalId, Term)>,
-        commit: usize,
+        patch: Vec<(Entry<Proposal>, ProposalId, Term)>,
+        (commit, round): (usize, u64),
         adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>,
         disk: &mut impl Persistor<Proposal>
     ) {
         // if term is outdated or log doesn't match, reply append failed
-        let reject = RaftPaperMsg::ReplicateRej {
-            from: self.id, 
-            term: self.term,
-            at: prefix_index
-        };
+        // - on a mismatch, the hint lets the leader skip the whole conflicting term at once
+        let (id, term) = (self.id, self.term);
+        let reject = |hint| RaftPaperMsg::ReplicateRej { from: id, term, at: prefix_index, hint };
         if leader_term < self.term {
             println!("RAFT :: {:?} :: reject replication because current term is bigger", self.id);
-            adaptor.send(leader_id, reject.clone());
+            adaptor.send(leader_id, reject((None, prefix_index)));
             return;
         }
         // if currently i'm not a follower in this term, convert to follower
+        // - the timer restarts within the first half of its bound, so that a follower
+        //   never times out right after hearing from the leader
         self.role = PaperRole::Follower { leader: leader_id };
-        self.timeout_elect = rand::random::<u64>() % self.bound_elect;
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+        self.timeout_lease = 0;
+        self.fail_reads(adaptor);
         if self.term < leader_term {
             self.term = leader_term;
             self.vote = None;
             disk.persist(self.term, self.vote);
         }
-        if prefix_term.is_some() && prefix_term != disk.term(prefix_index.checked_add_signed(-1).unwrap_or(0)) {
+        // entries covered by local snapshot are committed, so they always match
+        if prefix_term.is_some() && prefix_index >= disk.offset().1 && prefix_term != disk.term(prefix_index.checked_add_signed(-1).unwrap_or(0)) {
             println!("RAFT :: {:?} :: reject replication because prefix doesn't match", self.id);
-            adaptor.send(leader_id, reject);
+            adaptor.send(leader_id, reject(conflict(prefix_index, disk)));
             return;
         }
         // modify or update replicated entries
         // get the last synchronized entry
         let sync = disk.append(prefix_index, patch);
+        self.outcomes.discard(disk.discarded());
+        self.membership.scan(prefix_index, disk);
         // update commitable index
-        if commit >= self.commitable {
-            self.commitable = commit.min(disk.last().1);
-            disk.commit(commit.min(disk.last().1));
-        }
-        adaptor.send(leader_id, RaftPaperMsg::ReplicateAck { from: self.id, tail: disk.last().1, sync });
+        // - a short patch after a back off never lowers it
+        self.advance(commit.min(sync), disk);
+        adaptor.send(leader_id, RaftPaperMsg::ReplicateAck { from: self.id, tail: disk.last().1, sync, round: Some(round) });
     }
     // handle follower/candidate acknowledge
     pub(crate) fn handle_replicate_ack(&mut self,
         from: RaftId,
         sync: usize,
-        tail: usize,
+        _tail: usize,
+        round: Option<u64>,
+        adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>,
         disk: &mut impl Persistor<Proposal>
     ) {
-        let PaperRole::Leader { matched, guessed } = &mut self.role else { return };
+        let PaperRole::Leader { matched, guessed, installing, responded, .. } = &mut self.role else { return };
+        // servers outside the configuration are not tracked
+        if !matched.contains_key(&from) { return }
+        responded.insert(from);
+        installing.get_mut(&from).expect("every peer should be logged").0 = 0;
         *guessed.get_mut(&from).expect("every peer should be logged") = sync;
         *matched.get_mut(&from).expect("every peer should be logged") = sync;
-        let mut matches = matched.values().copied().collect::<Vec<_>>();
-        matches.sort();
-        self.commitable = self.commitable.max(matches[self.peers.len() / 2 - 1]);
-        disk.commit(self.commitable);
+        // learners are tracked as well, but only voters count toward the commit index
+        // - only an entry of the current term is committed by counting replicas, earlier entries follow it,
+        //   otherwise an entry on a majority can still be overwritten by a later leader (figure 8 of the paper)
+        let last = disk.last().1;
+        let committed = self.membership.config().committed(|x| if x == self.id { last } else { matched.get(&x).copied().unwrap_or(0) });
+        if committed > 0 && disk.term(committed - 1) == Some(self.term) { self.advance(committed, disk) }
+        self.reconfigured(adaptor, disk);
+        self.transferring(adaptor, disk);
+        if let Some(round) = round {
+            self.extend_lease(from, round);
+            self.confirm_reads(from, round, adaptor);
+        }
+    }
+    // push a configuration entry as the leader, and track new members
+    pub(crate) fn configure(&mut self, config: Config, id: ProposalId, disk: &mut impl Persistor<Proposal>) {
+        let PaperRole::Leader { matched, guessed, installing, .. } = &mut self.role else { return };
+        for x in config.members() {
+            matched.entry(x).or_insert(0);
+            guessed.entry(x).or_insert(disk.last().1);
+            installing.entry(x).or_insert((0, 0));
+        }
+        self.membership.push(disk.last().1, config.clone());
+        disk.push(Entry::Config(config), id, self.term);
+    }
+    // move on once the configuration in effect is committed
+    // - joint consensus C_old,new is followed by C_new
+    // - a leader outside C_new steps down
+    pub(crate) fn reconfigured(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let Some(at) = self.membership.index() else { return };
+        if at >= self.commitable { return }
+        let config = self.membership.config().clone();
+        if config.joint.is_some() {
+            // the outcome of the change is reported for C_old,new, C_new gets an id of its own
+            self.configure(config.leave(), ProposalId::leave(at), disk);
+        } else if !config.is_voter(self.id) {
+            println!("RAFT :: {:?} step down, removed from configuration", self.id);
+            self.role = PaperRole::Candidate { votes: HashSet::new() };
+            self.fail_reads(adaptor);
+        }
     }
     // handle follower/candidate rejection
     pub(crate) fn handle_replicate_rej(&mut self,
         from: RaftId,
         term: Term,
-        at: usize, disk: &mut impl Persistor<Proposal>
+        at: usize,
+        hint: (Option<Term>, usize),
+        adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>,
+        disk: &mut impl Persistor<Proposal>
     ) {
-        let PaperRole::Leader { guessed, .. } = &mut self.role else { return };
+        let PaperRole::Leader { guessed, responded, .. } = &mut self.role else { return };
+        // servers outside the configuration are not tracked
+        if !guessed.contains_key(&from) { return }
         if term <= self.term {
-            *guessed.get_mut(&from).expect("every peer should be logged") = at / 2;
+            responded.insert(from);
+            *guessed.get_mut(&from).expect("every peer should be logged") = if self.hint { backtrack(at, hint, disk) } else { at / 2 };
         } else {
-            self.role = PaperRole::Candidate { count: 0 };
+            self.role = PaperRole::Candidate { votes: HashSet::new() };
             self.term = term;
             self.vote = None;
             disk.persist(self.term, self.vote);
+            self.fail_reads(adaptor);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    #[test]
+    fn commit_sync() {
+        let network = Arc::new(Mutex::new(MockFIFONetwork::<RaftPaperMsg<usize>>::new(2)));
+        let adaptor = MockAdaptor::new(RaftId(1), network.clone());
+        let mut disk = MockPersistor::<usize>::new();
+        for (i, term) in [1, 2, 2, 2].into_iter().enumerate() { disk.push(Entry::Command(i), ProposalId(i as u64), Term(term)); }
+        let mut node = RaftPaperImpl::new(RaftId(1), 10, vec![RaftId(0)], 100, 2, &mut disk);
+        // the leader of term 3 only vouches for entries 0..3, the one at 3 may not be its own
+        node.handle_replicate((Term(3), RaftId(0)), (Some(Term(2)), 2), vec![(Entry::Command(2), ProposalId(2), Term(2))], (4, 0), &adaptor, &mut disk);
+        assert_eq!(node.commitable, 3);
+    }
+}
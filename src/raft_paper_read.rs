@@ -0,0 +1,120 @@
+use crate::*;
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+
+// A read on the leader, waiting for a quorum to acknowledge a replication round
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PendingRead {
+    pub(crate) id: ProposalId,
+    // the server that serves the read
+    pub(crate) from: RaftId,
+    pub(crate) index: usize,
+    // acknowledgements of this round or later count
+    pub(crate) round: u64,
+    pub(crate) acks: HashSet<RaftId>,
+}
+
+// Linearizable reads without going through the log (ReadIndex):
+// - The leader records its commit index as the read index.
+// - The leader confirms it is still the leader, once a quorum acknowledges a replication round
+//   sent after the read arrived.
+// - The read is safe once the state machine has applied up to the read index.
+// - A follower forwards the read to the leader, and waits for the read index on its own state machine.
+// Leader lease, see with_lease: 
+// - A leader holding a lease confirms reads at once, since no other leader can be elected meanwhile. 
+impl<Proposal> RaftPaperImpl<Proposal> where
+    Proposal: Serialize + for<'de> Deserialize<'de> + Clone
+{
+    // request a linearizable read, the id must be distinct
+    // - the read can be served once it is returned by take_reads, which also reports a failed read
+    pub fn read_index(&mut self, id: ProposalId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        match self.role {
+            PaperRole::Follower { leader } => {
+                adaptor.send(leader, RaftPaperMsg::ReadIndexReq { id, from: self.id });
+                Ok(())
+            }
+            PaperRole::Candidate { .. } | PaperRole::PreCandidate { .. } => Err(RaftErr::ReadFailed { id, leader: None }),
+            PaperRole::Leader { .. } => {
+                self.handle_read_index(id, self.id, adaptor, disk);
+                Ok(())
+            }
+        }
+    }
+    // reads that are safe to serve, given the index the state machine has applied up to,
+    // and reads that failed since the last call
+    // - a failed read can be retried, with the leader it carries if any
+    pub fn take_reads(&mut self, applied: usize) -> Vec<Result<ProposalId, RaftErr>> {
+        let (ready, waiting) = std::mem::take(&mut self.ready).into_iter().partition(|(_, index)| *index <= applied);
+        self.ready = waiting;
+        let failed = std::mem::take(&mut self.failed).into_iter().map(Err);
+        failed.chain(ready.into_iter().map(|(id, _)| Ok(id))).collect()
+    }
+    // handle a read as the leader
+    // - until an entry of its own term is committed, the leader may not know the latest commit index,
+    //   so it takes the end of its log, which holds every committed entry
+    // - a replication round confirms leadership
+    // - a server that is not the leader rejects the read, with the leader it knows of
+    pub(crate) fn handle_read_index(&mut self, id: ProposalId, from: RaftId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let PaperRole::Leader { .. } = self.role else { return self.reject_read(id, from, adaptor) };
+        let current = self.commitable > 0 && disk.term(self.commitable - 1) == Some(self.term);
+        if current && self.leased() { self.serve_read(id, from, self.commitable, adaptor); return }
+        let index = if current { self.commitable } else { disk.last().1 };
+        self.reads.push(PendingRead { id, from, index, round: self.round + 1, acks: HashSet::new() });
+        self.replicate(adaptor, disk);
+        self.confirm_reads(self.id, self.round, adaptor);
+    }
+    // count an acknowledgement of a replication round toward pending reads
+    pub(crate) fn confirm_reads(&mut self, from: RaftId, round: u64, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>) {
+        let PaperRole::Leader { .. } = self.role else { return };
+        let config = self.membership.config();
+        let mut confirmed = vec![];
+        self.reads.retain_mut(|read| {
+            if round >= read.round { read.acks.insert(from); }
+            read.acks.insert(self.id);
+            let quorum = config.quorum(|x| read.acks.contains(&x));
+            if quorum { confirmed.push((read.id, read.from, read.index)) }
+            !quorum
+        });
+        for (id, from, index) in confirmed { self.serve_read(id, from, index, adaptor) }
+    }
+    // hand a confirmed read index to the server that serves the read
+    fn serve_read(&mut self, id: ProposalId, from: RaftId, index: usize, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>) {
+        if from == self.id { self.ready.push((id, index)) }
+        else { adaptor.send(from, RaftPaperMsg::ReadIndexAck { id, index }) }
+    }
+    // whether this server is the leader and holds a lease
+    // - a quorum acknowledged rounds sent within bound_elect - margin ticks
+    // - there is no lease during leadership transfer
+    pub fn leased(&self) -> bool {
+        let (Some(margin), PaperRole::Leader { heard, .. }, None) = (self.lease, &self.role, self.transfer) else { return false };
+        let span = self.bound_elect.saturating_sub(margin);
+        self.membership.config().quorum(|x| x == self.id || heard.get(&x).is_some_and(|at| self.clock - at < span))
+    }
+    // record the tick a round acknowledged by a peer was sent at
+    pub(crate) fn extend_lease(&mut self, from: RaftId, round: u64) {
+        let PaperRole::Leader { heard, .. } = &mut self.role else { return };
+        let Ok(i) = self.sent.binary_search_by_key(&round, |(x, _)| *x) else { return };
+        let at = self.sent[i].1;
+        heard.entry(from).and_modify(|x| *x = at.max(*x)).or_insert(at);
+    }
+    // handle a read index confirmed by the leader
+    pub(crate) fn handle_read_index_ack(&mut self, id: ProposalId, index: usize) {
+        self.ready.push((id, index));
+    }
+}
+
+// failing reads needs no Clone, so that every step-down path can do it
+impl<Proposal> RaftPaperImpl<Proposal> where
+    Proposal: Serialize + for<'de> Deserialize<'de>
+{
+    // fail pending reads once the leader steps down, they can no longer be confirmed
+    pub(crate) fn fail_reads(&mut self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>) {
+        for read in std::mem::take(&mut self.reads) { self.reject_read(read.id, read.from, adaptor) }
+    }
+    // tell the server that serves a read that it failed
+    pub(crate) fn reject_read(&mut self, id: ProposalId, from: RaftId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>) {
+        let leader = match self.role { PaperRole::Follower { leader } => Some(leader), _ => None };
+        if from == self.id { self.failed.push(RaftErr::ReadFailed { id, leader }) }
+        else { adaptor.send(from, RaftPaperMsg::ReadIndexRej { id, leader }) }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::*;
+use serde::{Serialize, Deserialize};
+
+// Snapshot transfer to a follower that lags behind the snapshot point: 
+// - The leader streams the snapshot in chunks, one chunk per acknowledgement. 
+//   The current chunk is only resent if no acknowledgement arrived for a heartbeat interval. 
+// - The follower only accepts the chunk that continues its buffer, 
+//   otherwise it tells the leader where to continue. 
+// - A partial buffer survives leader changes, since a snapshot at the same point is the same state. 
+// - Once the last chunk arrives, the follower compacts its log and acknowledges replication. 
+impl<Proposal> RaftPaperImpl<Proposal> where
+    Proposal: Serialize + for<'de> Deserialize<'de> + Clone
+{
+    // send next snapshot chunk to a follower
+    pub(crate) fn install(&self, id: RaftId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let PaperRole::Leader { installing, .. } = &self.role else { return };
+        let Some(snapshot) = disk.snapshot() else { return };
+        let offset = installing[&id].0.min(snapshot.len());
+        let end = (offset + self.chunk).min(snapshot.len());
+        adaptor.send(id, RaftPaperMsg::InstallSnapshot {
+            leader: (self.term, self.id),
+            last: disk.offset(),
+            config: self.membership.base().clone(),
+            chunk: snapshot[offset..end].to_vec(),
+            done: end == snapshot.len(),
+            offset,
+        });
+    }
+    // receive a snapshot chunk
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn handle_install_snapshot(&mut self,
+        (leader_term, leader_id): (Term, RaftId),
+        (last_term, last_index): (Term, usize),
+        config: Config,
+        offset: usize,
+        chunk: Vec<u8>,
+        done: bool,
+        adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>,
+        disk: &mut impl Persistor<Proposal>
+    ) {
+        if leader_term < self.term {
+            println!("RAFT :: {:?} :: reject snapshot because current term is bigger", self.id);
+            adaptor.send(leader_id, RaftPaperMsg::ReplicateRej { from: self.id, term: self.term, at: last_index, hint: (None, last_index) });
+            return;
+        }
+        // if currently i'm not a follower in this term, convert to follower
+        self.role = PaperRole::Follower { leader: leader_id };
+        self.timeout_elect = rand::random::<u64>() % self.bound_elect.div_ceil(2);
+        self.timeout_lease = 0;
+        self.fail_reads(adaptor);
+        if self.term < leader_term {
+            self.term = leader_term;
+            self.vote = None;
+            disk.persist(self.term, self.vote);
+        }
+        // committed entries are never lost, nothing to install
+        if last_index <= self.commitable {
+            self.receiving = None;
+            adaptor.send(leader_id, RaftPaperMsg::ReplicateAck { from: self.id, sync: self.commitable, tail: disk.last().1, round: None });
+            return;
+        }
+        // start over on a different snapshot
+        if self.receiving.as_ref().is_none_or(|(point, _)| *point != (last_term, last_index)) {
+            self.receiving = Some(((last_term, last_index), vec![]));
+        }
+        let Some((_, buffer)) = &mut self.receiving else { unreachable!() };
+        let accept = offset == buffer.len();
+        if accept { buffer.extend(chunk); }
+        if !(accept && done) {
+            adaptor.send(leader_id, RaftPaperMsg::SnapshotAck { from: self.id, last: last_index, offset: buffer.len() });
+            return;
+        }
+        // the whole snapshot is received, install it
+        let Some((_, snapshot)) = self.receiving.take() else { unreachable!() };
+        println!("RAFT :: {:?} :: install snapshot at {last_index:?}", self.id);
+        // proposals in local log are committed if it matches the snapshot point, otherwise they are dropped
+        if disk.term(last_index - 1) == Some(last_term) { self.advance(last_index, disk) }
+        disk.compact(last_index, last_term, snapshot);
+        self.outcomes.discard(disk.discarded());
+        self.membership.install(config, disk);
+        self.advance(last_index, disk);
+        adaptor.send(leader_id, RaftPaperMsg::ReplicateAck { from: self.id, sync: last_index, tail: disk.last().1, round: None });
+    }
+    // handle snapshot acknowledge, continue from where the follower asks
+    pub(crate) fn handle_snapshot_ack(&mut self,
+        from: RaftId,
+        last: usize,
+        offset: usize,
+        adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>,
+        disk: &mut impl Persistor<Proposal>
+    ) {
+        let PaperRole::Leader { installing, responded, .. } = &mut self.role else { return };
+        // servers outside the configuration are not tracked
+        if !installing.contains_key(&from) { return }
+        responded.insert(from);
+        // an acknowledgement for an older snapshot, start over
+        let offset = if last == disk.offset().1 { offset } else { 0 };
+        *installing.get_mut(&from).expect("every peer should be logged") = (offset, self.clock);
+        self.install(from, adaptor, disk);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::*;
+use serde::{Serialize, Deserialize};
+
+// Leadership transfer, e.g. before maintenance: 
+// - The leader stops accepting proposals, so that the target can catch up with the whole log. 
+// - Once the target has matched the whole log, the leader sends TimeoutNow. 
+// - The target starts an election at once, and wins it with an up-to-date log and a newer term. 
+// - The transfer is aborted if the target does not win within an election timeout. 
+impl<Proposal> RaftPaperImpl<Proposal> where
+    Proposal: Serialize + for<'de> Deserialize<'de> + Clone
+{
+    // hand leadership over to a voter
+    pub fn transfer_leadership(&mut self, target: RaftId, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) -> Result<(), RaftErr> {
+        let PaperRole::Leader { .. } = self.role else { return Err(RaftErr::TransferFailed { target }) };
+        if target == self.id { return Ok(()) }
+        if !self.membership.config().is_voter(target) { return Err(RaftErr::TransferFailed { target }) }
+        println!("RAFT :: {:?} transfer leadership to {target:?}", self.id);
+        self.transfer = Some((target, 0));
+        self.replicate(adaptor, disk);
+        self.transferring(adaptor, disk);
+        Ok(())
+    }
+    // send TimeoutNow if the target has matched the whole log
+    pub(crate) fn transferring(&self, adaptor: &impl Adaptor<RaftPaperMsg<Proposal>>, disk: &mut impl Persistor<Proposal>) {
+        let (PaperRole::Leader { matched, .. }, Some((target, _))) = (&self.role, self.transfer) else { return };
+        if matched.get(&target).is_none_or(|x| *x < disk.last().1) { return }
+        adaptor.send(target, RaftPaperMsg::TimeoutNow { leader: (self.term, self.id) });
+    }
+    // count down a transfer in progress
+    // - it is over once this server is no longer the leader
+    // - it is aborted after an election timeout, and the leader accepts proposals again
+    // - rounds sent meanwhile do not count toward a lease, as the target may have won
+    pub(crate) fn transfer_tick(&mut self) {
+        let PaperRole::Leader { heard, .. } = &mut self.role else { self.transfer = None; return };
+        let Some((target, elapsed)) = &mut self.transfer else { return };
+        *elapsed += 1;
+        if *elapsed < self.bound_elect { return }
+        println!("RAFT :: {:?} abort transfer to {target:?}", self.id);
+        self.transfer = None;
+        self.sent.clear();
+        heard.clear();
+    }
+}